@@ -0,0 +1,112 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use regex::Regex;
+
+use crate::error::Error;
+
+// A value of "allowed_url" or "allowed_bid" in the config.
+//
+// "glob:" and "regex:" prefixed values must match the whole string. Other
+// values are literals, which are compared as a prefix for urls and exactly for
+// blog ids.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(value: &str) -> Result<Self, Error> {
+        if let Some(glob) = value.strip_prefix("glob:") {
+            let mut regex_str = String::from("^");
+            for c in glob.chars() {
+                match c {
+                    '*' => regex_str.push_str(".*"),
+                    '?' => regex_str.push('.'),
+                    _ => regex_str.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                }
+            }
+            regex_str.push('$');
+            Ok(Pattern::Regex(Regex::new(&regex_str)?))
+        } else if let Some(regex_str) = value.strip_prefix("regex:") {
+            Ok(Pattern::Regex(Regex::new(&format!("^(?:{})$", regex_str))?))
+        } else {
+            Ok(Pattern::Literal(value.to_owned()))
+        }
+    }
+
+    pub fn as_literal(&self) -> Option<&str> {
+        match self {
+            Pattern::Literal(s) => Some(s),
+            Pattern::Regex(_) => None,
+        }
+    }
+
+    pub fn matches_url(&self, url: &str) -> bool {
+        match self {
+            Pattern::Literal(s) => url.starts_with(s.as_str()),
+            Pattern::Regex(regex) => regex.is_match(url),
+        }
+    }
+
+    pub fn matches_bid(&self, blog_id: &str) -> bool {
+        match self {
+            Pattern::Literal(s) => s == blog_id,
+            Pattern::Regex(regex) => regex.is_match(blog_id),
+        }
+    }
+}
+
+pub fn get_literals(patterns: &[Pattern]) -> Vec<String> {
+    patterns
+        .iter()
+        .filter_map(|pattern| pattern.as_literal().map(str::to_owned))
+        .collect()
+}
+
+pub fn is_url_allowed(patterns: &[Pattern], url: &str) -> bool {
+    patterns.iter().any(|pattern| pattern.matches_url(url))
+}
+
+pub fn canonical_url(blog_url_template: &str, blog_id: &str) -> String {
+    blog_url_template.replace("{blog_id}", blog_id)
+}
+
+// Checks a blog id that is not registered in the db. It must match one of the
+// "glob:" or "regex:" patterns, and if "blog_url_template" is set, the
+// blog_url (without its fragment) must be the blog id's canonical url.
+pub fn is_unregistered_bid_allowed(
+    patterns: &[Pattern],
+    blog_url_template: Option<&str>,
+    blog_id: &str,
+    blog_url: &str,
+) -> bool {
+    let matches_pattern: bool = patterns
+        .iter()
+        .any(|pattern| matches!(pattern, Pattern::Regex(_)) && pattern.matches_bid(blog_id));
+
+    if !matches_pattern {
+        return false;
+    }
+
+    if let Some(template) = blog_url_template {
+        let blog_url_no_fragment = blog_url.split('#').next().unwrap_or(blog_url);
+        blog_url_no_fragment == canonical_url(template, blog_id)
+    } else {
+        true
+    }
+}
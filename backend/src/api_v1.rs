@@ -0,0 +1,790 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::sync::Arc;
+
+use reqwest::Url;
+use salvo::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Config, allowlist,
+    comment_cache::CommentCache,
+    error::{Error, ErrorCode, JSON_ERRORS_KEY},
+    events::{EventHub, EventKind},
+    http_cache,
+    markdown::Renderer,
+    pagination, search,
+    sql::{self, SQLCtx},
+    thread,
+};
+
+const SESSION_COOKIE: &str = "comments_session";
+
+pub fn router() -> Router {
+    Router::with_path("api/v1")
+        .hoop(json_errors)
+        .push(
+            Router::with_path("blogs/{blog_id}/comments")
+                .get(list_comments)
+                .post(create_comment),
+        )
+        .push(Router::with_path("blogs/{blog_id}/events").get(comment_events))
+        .push(Router::with_path("search").get(search_comments))
+        .push(
+            Router::with_path("comments/{comment_id}")
+                .get(get_comment)
+                .patch(edit_comment)
+                .delete(delete_comment),
+        )
+        .push(
+            Router::with_path("comments/{comment_id}/reactions/{emoji}")
+                .put(add_reaction)
+                .delete(remove_reaction),
+        )
+        .push(
+            Router::with_path("session")
+                .get(get_session)
+                .delete(end_session),
+        )
+        .push(Router::with_path("login").get(start_login))
+        .push(Router::with_path("login/callback").get(finish_login))
+}
+
+#[handler]
+async fn json_errors(depot: &mut Depot) {
+    depot.insert(JSON_ERRORS_KEY, true);
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    format: Option<Format>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+    order: Option<pagination::Order>,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+enum Format {
+    Flat,
+    Tree,
+}
+
+#[derive(Deserialize)]
+struct NewComment {
+    comment: String,
+    parent_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CommentEdit {
+    comment: String,
+}
+
+#[derive(Deserialize)]
+struct DeleteQuery {
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LoginQuery {
+    redirect: String,
+}
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+    state: String,
+    code: String,
+}
+
+#[derive(Serialize)]
+struct CommentResponse {
+    blog_id: String,
+    #[serde(flatten)]
+    comment: sql::Comment,
+}
+
+#[derive(Serialize)]
+struct SessionResponse {
+    username: String,
+    userlogin: String,
+    userurl: String,
+    useravatar: String,
+    admin: bool,
+}
+
+// The bearer token takes precedence over the cookie.
+fn get_session_id(req: &Request) -> Option<String> {
+    if let Some(auth) = req.header::<String>("authorization")
+        && let Some(token) = auth.strip_prefix("Bearer ")
+    {
+        return Some(token.trim().to_owned());
+    }
+
+    let cookies: String = req.header("cookie")?;
+    cookies.split(';').find_map(|cookie| {
+        let (name, val) = cookie.trim().split_once('=')?;
+        (name == SESSION_COOKIE).then(|| val.to_owned())
+    })
+}
+
+fn require_login(req: &Request, config: &Config, sql_ctx: SQLCtx) -> Result<sql::LoginInfo, Error> {
+    let session_id = get_session_id(req)
+        .ok_or_else(|| Error::coded(ErrorCode::NotLoggedIn, "Not logged in!"))?;
+
+    crate::get_login(req, config, sql_ctx, &session_id)?
+        .ok_or_else(|| Error::coded(ErrorCode::SessionExpired, "Session is invalid or expired!"))
+}
+
+// Viewers don't need to be logged in, so invalid sessions are ignored.
+fn get_viewer(
+    req: &Request,
+    config: &Config,
+    sql_ctx: SQLCtx,
+) -> Result<Option<sql::LoginInfo>, Error> {
+    match get_session_id(req) {
+        Some(session_id) => crate::get_login(req, config, sql_ctx, &session_id),
+        None => Ok(None),
+    }
+}
+
+// Responses that mark the viewer's reactions depend on the session, so caches
+// must keep them apart.
+fn set_vary_viewer(res: &mut Response) -> Result<(), Error> {
+    res.add_header("Vary", "Authorization, Cookie", true)
+        .map_err(|_| Error::from("Failed to set Vary header!"))?;
+    Ok(())
+}
+
+fn get_path_param(req: &Request, name: &str) -> Result<String, Error> {
+    req.param(name).ok_or_else(|| {
+        Error::coded(
+            ErrorCode::BadRequest,
+            format!("Missing path parameter \"{}\"!", name),
+        )
+    })
+}
+
+// Deleted comments are hidden if hide_deleted_comments is set, matching the
+// listings.
+fn find_comment(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    comment_id: &str,
+) -> Result<CommentResponse, Error> {
+    match sql::get_comment(sql_ctx.clone(), comment_id, &config.reactions)? {
+        Some((_, comment)) if comment.deleted && config.hide_deleted_comments => Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist!",
+        )),
+        Some((blog_id, mut comment)) => {
+            let blog: Option<sql::Blog> = sql::get_blog(
+                sql_ctx,
+                &blog_id,
+                config.auto_close_days,
+                config.auto_close_from,
+            )?;
+            crate::set_badges(
+                config,
+                std::slice::from_mut(&mut comment),
+                blog.as_ref().and_then(|blog| blog.author.as_deref()),
+            );
+            Ok(CommentResponse { blog_id, comment })
+        }
+        None => Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist!",
+        )),
+    }
+}
+
+fn set_session_cookie(res: &mut Response, value: &str, max_age: u64) -> Result<(), Error> {
+    res.add_header(
+        "Set-Cookie",
+        format!(
+            "{}={}; Max-Age={}; Path=/; HttpOnly; Secure; SameSite=Lax",
+            SESSION_COOKIE, value, max_age
+        ),
+        true,
+    )
+    .map_err(|_| Error::from("Failed to set Set-Cookie header!"))?;
+
+    Ok(())
+}
+
+#[handler]
+async fn list_comments(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let blog_id: String = get_path_param(req, "blog_id")?;
+    let query: ListQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+
+    let limit: usize = query.limit.unwrap_or(pagination::MAX_LIMIT);
+    if limit == 0 || limit > pagination::MAX_LIMIT {
+        return Err(Error::coded(
+            ErrorCode::BadRequest,
+            format!("limit must be between 1 and {}!", pagination::MAX_LIMIT),
+        ));
+    }
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let closed: bool = crate::is_blog_closed(config, sql_ctx.clone(), &blog_id)?;
+
+    let viewer_id: Option<u64> =
+        get_viewer(req, config, sql_ctx.clone())?.map(|login| login.user_github_id);
+    set_vary_viewer(res)?;
+
+    let version: sql::CommentsVersion = sql::get_comments_version(sql_ctx.clone(), &blog_id)?;
+    let etag: String = http_cache::make_etag(&(
+        &blog_id,
+        &version,
+        closed,
+        query.format,
+        limit,
+        &query.cursor,
+        query.order,
+        viewer_id,
+    ));
+    if http_cache::check_not_modified(
+        req,
+        res,
+        &http_cache::viewer_cache_control(&config.comments_cache_control, viewer_id.is_some()),
+        &etag,
+        version.last_modified,
+    )? {
+        return Ok(());
+    }
+
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    let (mut comments, next_cursor, total) = crate::fetch_comment_page(
+        config,
+        sql_ctx.clone(),
+        renderer,
+        &blog_id,
+        query.order.unwrap_or(pagination::Order::Oldest),
+        limit,
+        query.cursor.as_deref(),
+    )?;
+    if let Some(viewer_id) = viewer_id {
+        crate::mark_reacted(
+            &mut comments,
+            &sql::get_user_reactions(sql_ctx, &blog_id, viewer_id)?,
+        );
+    }
+
+    if query.format == Some(Format::Tree) {
+        res.render(Json(pagination::Page {
+            comments: thread::to_tree(comments),
+            next_cursor,
+            total,
+            closed,
+        }));
+    } else {
+        res.render(Json(pagination::Page {
+            comments,
+            next_cursor,
+            total,
+            closed,
+        }));
+    }
+
+    Ok(())
+}
+
+#[handler]
+async fn create_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let blog_id: String = get_path_param(req, "blog_id")?;
+    let new_comment: NewComment = req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login: sql::LoginInfo = require_login(req, config, sql_ctx.clone())?;
+
+    if let Some(blog) = crate::get_blog(config, sql_ctx.clone(), &blog_id)? {
+        if blog.closed {
+            return Err(crate::closed_error(&blog_id));
+        }
+    } else if !allowlist::is_unregistered_bid_allowed(&config.allowed_bids, None, &blog_id, "") {
+        // Unregistered blogs are registered on their first comment.
+        return Err(Error::coded(
+            ErrorCode::BlogNotAllowed,
+            "blog_id is not allowed.",
+        ));
+    }
+
+    if let Some(parent_id) = &new_comment.parent_id
+        && let Some(reason) =
+            crate::check_reply_parent(config, sql_ctx.clone(), &blog_id, parent_id)?
+    {
+        return Err(Error::coded(ErrorCode::InvalidReply, reason));
+    }
+
+    let comment: String = crate::clean_comment(config, &new_comment.comment)?;
+
+    let pseudo_comment: sql::PseudoComment = sql::add_comment_as_user(
+        sql_ctx.clone(),
+        &login,
+        &blog_id,
+        new_comment.parent_id.as_deref(),
+        &comment,
+        config.blog_url_template.as_deref(),
+    )?;
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&pseudo_comment.blog_post_id);
+
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(
+        sql_ctx.clone(),
+        &pseudo_comment.blog_post_id,
+        EventKind::Created,
+        &pseudo_comment.comment_id,
+    );
+
+    crate::run_on_comment_cmds(config, &pseudo_comment);
+
+    let mut created: CommentResponse = find_comment(config, sql_ctx, &pseudo_comment.comment_id)?;
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    renderer.render_comment(&mut created.comment);
+
+    res.status_code(StatusCode::CREATED);
+    res.render(Json(created));
+
+    Ok(())
+}
+
+#[handler]
+async fn get_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = get_path_param(req, "comment_id")?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let mut comment: CommentResponse = find_comment(config, sql_ctx.clone(), &comment_id)?;
+
+    let viewer_id: Option<u64> =
+        get_viewer(req, config, sql_ctx.clone())?.map(|login| login.user_github_id);
+    set_vary_viewer(res)?;
+
+    let version: sql::CommentsVersion =
+        sql::get_comments_version(sql_ctx.clone(), &comment.blog_id)?;
+    let etag: String = http_cache::make_etag(&(&comment_id, &version, viewer_id));
+    if http_cache::check_not_modified(
+        req,
+        res,
+        &http_cache::viewer_cache_control(&config.comments_cache_control, viewer_id.is_some()),
+        &etag,
+        version.last_modified,
+    )? {
+        return Ok(());
+    }
+
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    renderer.render_comment(&mut comment.comment);
+    if let Some(viewer_id) = viewer_id {
+        crate::mark_reacted(
+            std::slice::from_mut(&mut comment.comment),
+            &sql::get_user_reactions(sql_ctx, &comment.blog_id, viewer_id)?,
+        );
+    }
+
+    res.render(Json(comment));
+
+    Ok(())
+}
+
+// Responds with the comment, with its reactions updated.
+async fn change_reaction(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+    add: bool,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = get_path_param(req, "comment_id")?;
+    let emoji: String = get_path_param(req, "emoji")?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login: sql::LoginInfo = require_login(req, config, sql_ctx.clone())?;
+
+    let blog_id: String =
+        crate::set_reaction(config, sql_ctx.clone(), &login, &comment_id, &emoji, add)?;
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&blog_id);
+
+    let mut comment: CommentResponse = find_comment(config, sql_ctx.clone(), &comment_id)?;
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    renderer.render_comment(&mut comment.comment);
+    crate::mark_reacted(
+        std::slice::from_mut(&mut comment.comment),
+        &sql::get_user_reactions(sql_ctx, &blog_id, login.user_github_id)?,
+    );
+
+    res.render(Json(comment));
+
+    Ok(())
+}
+
+#[handler]
+async fn add_reaction(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    change_reaction(req, res, depot, true).await
+}
+
+#[handler]
+async fn remove_reaction(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    change_reaction(req, res, depot, false).await
+}
+
+#[handler]
+async fn edit_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = get_path_param(req, "comment_id")?;
+    let edit: CommentEdit = req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login: sql::LoginInfo = require_login(req, config, sql_ctx.clone())?;
+
+    let comment: String = crate::clean_comment(config, &edit.comment)?;
+
+    let existing: CommentResponse = find_comment(config, sql_ctx.clone(), &comment_id)?;
+    if existing.comment.deleted {
+        return Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment was deleted!",
+        ));
+    }
+    if crate::is_blog_closed(config, sql_ctx.clone(), &existing.blog_id)? {
+        return Err(crate::closed_error(&existing.blog_id));
+    }
+    if !sql::check_edit_comment_auth(
+        sql_ctx.clone(),
+        &comment_id,
+        &login.user_github_id.to_string(),
+    )? {
+        eprintln!(
+            "User tried to edit comment they didn't make! {}",
+            &comment_id
+        );
+        return Err(Error::coded(
+            ErrorCode::NotOwner,
+            "You are not the commenter of this comment.",
+        ));
+    }
+
+    if !sql::edit_comment_by_user(sql_ctx.clone(), &comment_id, login.user_github_id, &comment)? {
+        return Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment was deleted!",
+        ));
+    }
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&existing.blog_id);
+
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(
+        sql_ctx.clone(),
+        &existing.blog_id,
+        EventKind::Edited,
+        &comment_id,
+    );
+
+    let mut edited: CommentResponse = find_comment(config, sql_ctx, &comment_id)?;
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    renderer.render_comment(&mut edited.comment);
+
+    res.render(Json(edited));
+
+    Ok(())
+}
+
+#[handler]
+async fn delete_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = get_path_param(req, "comment_id")?;
+    let query: DeleteQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login: sql::LoginInfo = require_login(req, config, sql_ctx.clone())?;
+
+    let deleted: bool = if crate::is_admin(config, &login.userlogin) {
+        sql::try_delete_comment_id_only(
+            sql_ctx.clone(),
+            &comment_id,
+            &login.userlogin,
+            query.reason.as_deref(),
+            None,
+        )?
+    } else {
+        let existing: CommentResponse = find_comment(config, sql_ctx.clone(), &comment_id)?;
+        if !existing.comment.deleted
+            && !sql::check_edit_comment_auth(
+                sql_ctx.clone(),
+                &comment_id,
+                &login.user_github_id.to_string(),
+            )?
+        {
+            eprintln!(
+                "User tried to delete comment they didn't make! {}",
+                &comment_id
+            );
+            return Err(Error::coded(
+                ErrorCode::NotOwner,
+                "You are not the commenter of this comment.",
+            ));
+        }
+        sql::try_delete_comment(
+            sql_ctx.clone(),
+            &comment_id,
+            login.user_github_id,
+            &login.userlogin,
+            query.reason.as_deref(),
+            None,
+        )?
+    };
+
+    if !deleted {
+        return Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist or was already deleted!",
+        ));
+    }
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    let blog_id: String = crate::invalidate_comment_blog(cache, sql_ctx.clone(), &comment_id)?;
+
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(sql_ctx, &blog_id, EventKind::Deleted, &comment_id);
+
+    res.status_code(StatusCode::NO_CONTENT);
+
+    Ok(())
+}
+
+#[handler]
+async fn comment_events(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let blog_id: String = get_path_param(req, "blog_id")?;
+
+    crate::stream_comment_events(req, res, depot, blog_id);
+
+    Ok(())
+}
+
+#[handler]
+async fn search_comments(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let query: search::SearchQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    if !config.public_search {
+        let login: sql::LoginInfo = require_login(req, config, sql_ctx.clone())?;
+        if !crate::is_admin(config, &login.userlogin) {
+            return Err(Error::coded(ErrorCode::NotAdmin, "Not an admin!"));
+        }
+    }
+
+    res.render(Json(search::run(sql_ctx, &query)?));
+
+    Ok(())
+}
+
+#[handler]
+async fn get_session(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let login: sql::LoginInfo = require_login(req, config, config.into())?;
+
+    res.render(Json(SessionResponse {
+        admin: crate::is_admin(config, &login.userlogin),
+        username: login.username,
+        userlogin: login.userlogin,
+        userurl: login.userurl,
+        useravatar: login.useravatar,
+    }));
+
+    Ok(())
+}
+
+#[handler]
+async fn end_session(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    if let Some(session_id) = get_session_id(req) {
+        let client_ip: String = crate::get_client_ip(req, config);
+        sql::logout(config.into(), &session_id, &client_ip)?;
+    }
+
+    set_session_cookie(res, "", 0)?;
+    res.status_code(StatusCode::NO_CONTENT);
+
+    Ok(())
+}
+
+// Starts a Github login that returns to "redirect" with the session id in the
+// url fragment as "login_id", and in a cookie.
+#[handler]
+async fn start_login(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let query: LoginQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+    if !allowlist::is_url_allowed(&config.allowed_urls, &query.redirect) {
+        eprintln!("Client redirect url is invalid! {}", query.redirect);
+        return Err(Error::coded(
+            ErrorCode::UrlNotAllowed,
+            "redirect is not an allowed url!",
+        ));
+    }
+
+    let state = sql::create_pending_action(
+        config.into(),
+        sql::PendingAction::Login,
+        &query.redirect,
+        None,
+    )?;
+
+    let github_api_url = Url::parse_with_params(
+        "https://github.com/login/oauth/authorize",
+        &[
+            ("client_id", config.oauth_user.as_str()),
+            ("state", state.as_str()),
+            (
+                "redirect_uri",
+                &format!("{}/api/v1/login/callback", config.base_url),
+            ),
+        ],
+    )
+    .map_err(|_| Error::from("Failed to parse github api url!"))?;
+
+    res.render(Redirect::found(github_api_url.as_str()));
+
+    Ok(())
+}
+
+#[handler]
+async fn finish_login(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let query: CallbackQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let Some(redirect) =
+        sql::get_pending_action_target(sql_ctx.clone(), &query.state, sql::PendingAction::Login)?
+    else {
+        eprintln!("State is invalid (timed out?)!\n");
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Took too long to log in!",
+        ));
+    };
+    sql::remove_pending_action(sql_ctx.clone(), &query.state)?;
+
+    let user: crate::GithubUser = crate::fetch_github_user(
+        config,
+        &query.code,
+        &format!("{}/api/v1/login/callback", config.base_url),
+    )
+    .await?;
+
+    let login_id = sql::add_login(
+        sql_ctx,
+        if config.x_real_ip_enabled {
+            req.header("x-real-ip")
+        } else {
+            None
+        },
+        user.id,
+        &user.name,
+        &user.login,
+        &user.url,
+        &user.avatar_url,
+    )?;
+
+    let mut redirect_url =
+        Url::parse(&redirect).map_err(|_| Error::from("Failed to parse redirect url!"))?;
+    redirect_url.set_fragment(Some(&format!("login_id={}", login_id)));
+
+    set_session_cookie(res, &login_id, config.get_login_timeout() * 60)?;
+    res.render(Redirect::found(redirect_url.as_str()));
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex, MutexGuard,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{error::Error, sql::Comment};
+
+struct Entry {
+    comments: Arc<Vec<Comment>>,
+    created: Instant,
+    last_used: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheStats {
+    pub entries: usize,
+    pub max_entries: usize,
+    pub ttl_seconds: u64,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+// Caches the comments of each blog_id as returned by
+// sql::get_comments_per_blog_id(). Writers must call invalidate() after
+// changing a blog's comments. The least recently used entry is evicted when
+// full, and a "max_entries" of 0 disables the cache.
+pub struct CommentCache {
+    entries: Mutex<HashMap<String, Entry>>,
+    max_entries: usize,
+    ttl: Duration,
+    // Bumped on every invalidation, so that comments fetched before an
+    // invalidation are not cached after it.
+    generation: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CommentCache {
+    pub fn new(max_entries: usize, ttl: Duration) -> Self {
+        CommentCache {
+            entries: Mutex::new(HashMap::new()),
+            max_entries,
+            ttl,
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Entry>> {
+        // The map is valid even if a holder panicked.
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn get_or_fetch<F>(&self, blog_id: &str, fetch: F) -> Result<Arc<Vec<Comment>>, Error>
+    where
+        F: FnOnce() -> Result<Vec<Comment>, Error>,
+    {
+        if self.max_entries == 0 {
+            return fetch().map(Arc::new);
+        }
+
+        {
+            let mut entries = self.lock();
+            if let Some(entry) = entries.get_mut(blog_id) {
+                if entry.created.elapsed() < self.ttl {
+                    entry.last_used = Instant::now();
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    return Ok(entry.comments.clone());
+                }
+                entries.remove(blog_id);
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let generation: u64 = self.generation.load(Ordering::Acquire);
+        let comments: Arc<Vec<Comment>> = Arc::new(fetch()?);
+
+        let mut entries = self.lock();
+        if self.generation.load(Ordering::Acquire) != generation {
+            return Ok(comments);
+        }
+        if entries.len() >= self.max_entries && !entries.contains_key(blog_id) {
+            let oldest: Option<String> = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.to_owned());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let now = Instant::now();
+        entries.insert(
+            blog_id.to_owned(),
+            Entry {
+                comments: comments.clone(),
+                created: now,
+                last_used: now,
+            },
+        );
+
+        Ok(comments)
+    }
+
+    pub fn invalidate(&self, blog_id: &str) {
+        let mut entries = self.lock();
+        self.generation.fetch_add(1, Ordering::Release);
+        entries.remove(blog_id);
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        self.generation.fetch_add(1, Ordering::Release);
+        entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.lock().len(),
+            max_entries: self.max_entries,
+            ttl_seconds: self.ttl.as_secs(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
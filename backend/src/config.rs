@@ -20,9 +20,115 @@ use std::{
     path::Path,
 };
 
-use crate::error::Error;
+use time::{UtcOffset, macros::format_description};
+
+use crate::{allowlist::Pattern, error::Error};
 
 const DEFAULT_LOGIN_TIMEOUT_MINUTES: u64 = 60;
+const DEFAULT_MAINTENANCE_INTERVAL_SECONDS: u64 = 300;
+const DEFAULT_DELETED_RETENTION_DAYS: u64 = 30;
+const DEFAULT_MAX_REPLY_DEPTH: u64 = 5;
+const DEFAULT_COMMENT_COUNTS_MAX_AGE_SECONDS: u64 = 60;
+// Caches must revalidate with the ETag before every use.
+const DEFAULT_COMMENTS_CACHE_CONTROL: &str = "no-cache";
+// Matches the comment anchors of the example frontend.
+const DEFAULT_PERMALINK_FRAGMENT: &str = "{blog_id}comment{comment_id}";
+const DEFAULT_COMMENT_CACHE_SIZE: usize = 100;
+const DEFAULT_COMMENT_CACHE_TTL_SECONDS: u64 = 60;
+const DEFAULT_EVENTS_HEARTBEAT_SECONDS: u64 = 15;
+const DEFAULT_EVENTS_RELAY_INTERVAL_MS: u64 = 1000;
+// The size of the TEXT column comments are stored in.
+const MAX_COMMENT_BYTES: usize = 65535;
+const DEFAULT_MAX_COMMENT_CHARS: usize = 65000;
+const DEFAULT_MAX_BODY_SIZE: usize = 256 * 1024;
+const DEFAULT_REACTIONS: &[&str] = &["👍", "👎", "😄", "🎉", "😕", "❤️", "🚀", "👀"];
+// The size of the REACTION.emoji column.
+const MAX_REACTION_CHARS: usize = 32;
+const DEFAULT_MARKDOWN_EXTENSIONS: &[MarkdownExtension] = &[
+    MarkdownExtension::Strikethrough,
+    MarkdownExtension::Tables,
+    MarkdownExtension::Tasklists,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutoCloseFrom {
+    #[default]
+    FirstComment,
+    PostDate,
+}
+
+impl AutoCloseFrom {
+    fn parse(val: &str) -> Result<Self, Error> {
+        match val {
+            "first_comment" => Ok(AutoCloseFrom::FirstComment),
+            "post_date" => Ok(AutoCloseFrom::PostDate),
+            _ => Err(format!(
+                "auto_close_from must be \"first_comment\" or \"post_date\", got \"{}\"!",
+                val
+            )
+            .into()),
+        }
+    }
+}
+
+// The GFM extensions that can be enabled on top of CommonMark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarkdownExtension {
+    Strikethrough,
+    Tables,
+    Tasklists,
+}
+
+impl MarkdownExtension {
+    // An empty list enables none of them.
+    fn parse_list(val: &str) -> Result<Vec<Self>, Error> {
+        let mut extensions: Vec<Self> = Vec::new();
+        for name in val
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            extensions.push(match name {
+                "strikethrough" => MarkdownExtension::Strikethrough,
+                "tables" => MarkdownExtension::Tables,
+                "tasklists" => MarkdownExtension::Tasklists,
+                _ => {
+                    return Err(format!(
+                        "markdown_extensions must only contain \"strikethrough\", \"tables\" or \"tasklists\", got \"{}\"!",
+                        name
+                    )
+                    .into());
+                }
+            });
+        }
+
+        Ok(extensions)
+    }
+}
+
+// 0 disables max_lines and max_links.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommentLimits {
+    pub min_chars: usize,
+    pub max_chars: usize,
+    pub min_bytes: usize,
+    pub max_bytes: usize,
+    pub max_lines: usize,
+    pub max_links: usize,
+}
+
+impl Default for CommentLimits {
+    fn default() -> Self {
+        CommentLimits {
+            min_chars: 1,
+            max_chars: DEFAULT_MAX_COMMENT_CHARS,
+            min_bytes: 1,
+            max_bytes: MAX_COMMENT_BYTES,
+            max_lines: 0,
+            max_links: 0,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -36,13 +142,37 @@ pub struct Config {
     oauth_user: String,
     oauth_token: String,
     base_url: String,
-    allowed_urls: Vec<String>,
-    allowed_bids: Vec<String>,
+    allowed_urls: Vec<Pattern>,
+    allowed_bids: Vec<Pattern>,
+    blog_url_template: Option<String>,
     user_agent: String,
     on_comment_cmds: Vec<String>,
     admins: Vec<String>,
+    moderators: Vec<String>,
     login_timeout: u64,
     x_real_ip_enabled: bool,
+    maintenance_interval: u64,
+    hide_deleted_comments: bool,
+    deleted_retention_days: u64,
+    public_revision_history: bool,
+    timestamp_migration_offset: Option<String>,
+    auto_close_days: u64,
+    auto_close_from: AutoCloseFrom,
+    max_reply_depth: u64,
+    comment_counts_max_age: u64,
+    comments_cache_control: String,
+    permalink_fragment: String,
+    comment_cache_size: usize,
+    comment_cache_ttl: u64,
+    markdown_extensions: Vec<MarkdownExtension>,
+    comment_limits: CommentLimits,
+    max_body_size: usize,
+    check_confusable_names: bool,
+    reactions: Vec<String>,
+    events_heartbeat_seconds: u64,
+    events_relay: bool,
+    events_relay_interval_ms: u64,
+    public_search: bool,
 }
 
 impl Config {
@@ -88,14 +218,18 @@ impl Config {
         &self.base_url
     }
 
-    pub fn get_allowed_urls(&self) -> &[String] {
+    pub fn get_allowed_urls(&self) -> &[Pattern] {
         &self.allowed_urls
     }
 
-    pub fn get_allowed_bids(&self) -> &[String] {
+    pub fn get_allowed_bids(&self) -> &[Pattern] {
         &self.allowed_bids
     }
 
+    pub fn get_blog_url_template(&self) -> Option<&str> {
+        self.blog_url_template.as_deref()
+    }
+
     pub fn get_user_agent(&self) -> &str {
         &self.user_agent
     }
@@ -108,6 +242,10 @@ impl Config {
         &self.admins
     }
 
+    pub fn get_moderators(&self) -> &[String] {
+        &self.moderators
+    }
+
     pub fn get_login_timeout(&self) -> u64 {
         self.login_timeout
     }
@@ -115,6 +253,129 @@ impl Config {
     pub fn get_x_real_ip_enabled(&self) -> bool {
         self.x_real_ip_enabled
     }
+
+    pub fn get_maintenance_interval(&self) -> u64 {
+        self.maintenance_interval
+    }
+
+    pub fn get_hide_deleted_comments(&self) -> bool {
+        self.hide_deleted_comments
+    }
+
+    pub fn get_deleted_retention_days(&self) -> u64 {
+        self.deleted_retention_days
+    }
+
+    pub fn get_public_revision_history(&self) -> bool {
+        self.public_revision_history
+    }
+
+    pub fn get_timestamp_migration_offset(&self) -> Option<&str> {
+        self.timestamp_migration_offset.as_deref()
+    }
+
+    pub fn get_auto_close_days(&self) -> u64 {
+        self.auto_close_days
+    }
+
+    pub fn get_auto_close_from(&self) -> AutoCloseFrom {
+        self.auto_close_from
+    }
+
+    pub fn get_max_reply_depth(&self) -> u64 {
+        self.max_reply_depth
+    }
+
+    pub fn get_comment_counts_max_age(&self) -> u64 {
+        self.comment_counts_max_age
+    }
+
+    pub fn get_comments_cache_control(&self) -> &str {
+        &self.comments_cache_control
+    }
+
+    pub fn get_permalink_fragment(&self) -> &str {
+        &self.permalink_fragment
+    }
+
+    pub fn get_comment_cache_size(&self) -> usize {
+        self.comment_cache_size
+    }
+
+    pub fn get_comment_cache_ttl(&self) -> u64 {
+        self.comment_cache_ttl
+    }
+
+    pub fn get_markdown_extensions(&self) -> &[MarkdownExtension] {
+        &self.markdown_extensions
+    }
+
+    pub fn get_comment_limits(&self) -> CommentLimits {
+        self.comment_limits
+    }
+
+    pub fn get_max_body_size(&self) -> usize {
+        self.max_body_size
+    }
+
+    pub fn get_check_confusable_names(&self) -> bool {
+        self.check_confusable_names
+    }
+
+    pub fn get_reactions(&self) -> &[String] {
+        &self.reactions
+    }
+
+    pub fn get_events_heartbeat_seconds(&self) -> u64 {
+        self.events_heartbeat_seconds
+    }
+
+    pub fn get_events_relay(&self) -> bool {
+        self.events_relay
+    }
+
+    pub fn get_events_relay_interval_ms(&self) -> u64 {
+        self.events_relay_interval_ms
+    }
+
+    pub fn get_public_search(&self) -> bool {
+        self.public_search
+    }
+}
+
+// An empty list disables reactions.
+fn parse_reactions(val: &str) -> Result<Vec<String>, Error> {
+    let mut reactions: Vec<String> = Vec::new();
+    for emoji in val
+        .split(',')
+        .map(str::trim)
+        .filter(|emoji| !emoji.is_empty())
+    {
+        if emoji.chars().count() > MAX_REACTION_CHARS {
+            return Err(format!(
+                "Reactions can be at most {} characters, got \"{}\"!",
+                MAX_REACTION_CHARS, emoji
+            )
+            .into());
+        }
+        if !reactions.iter().any(|reaction| reaction == emoji) {
+            reactions.push(emoji.to_owned());
+        }
+    }
+
+    Ok(reactions)
+}
+
+// Normalizes an offset like "+9:00" or "-05:30" into the "+09:00" form that
+// the db server expects.
+fn parse_utc_offset(val: &str) -> Result<String, Error> {
+    let format = format_description!("[offset_hour sign:mandatory padding:none]:[offset_minute]");
+    let offset = UtcOffset::parse(val, format)
+        .map_err(|_| Error::from(format!("Invalid UTC offset \"{}\"!", val)))?;
+
+    Ok(offset.format(format_description!(
+        "[offset_hour sign:mandatory]:[offset_minute]"
+    ))?)
 }
 
 impl TryFrom<&Path> for Config {
@@ -122,7 +383,10 @@ impl TryFrom<&Path> for Config {
 
     fn try_from(value: &Path) -> Result<Self, Self::Error> {
         let file = File::open(value)?;
-        let file_buffered = BufReader::new(file);
+        let mut file_buffered = BufReader::new(file);
+        // Read as UTF-8, as values like the reactions can be emoji.
+        let mut contents: String = String::new();
+        file_buffered.read_to_string(&mut contents)?;
 
         let mut sql_user: Result<String, Self::Error> = Err("sql_user not specified!".into());
         let mut sql_pass: Result<String, Self::Error> = Err("sql_pass not specified!".into());
@@ -134,23 +398,64 @@ impl TryFrom<&Path> for Config {
         let mut oauth_user: Result<String, Self::Error> = Err("oauth_user not specified!".into());
         let mut oauth_token: Result<String, Self::Error> = Err("oauth_token not specified!".into());
         let mut base_url: Result<String, Self::Error> = Err("base_url not specified!".into());
-        let mut allowed_urls: Vec<String> = Vec::new();
-        let mut allowed_bids: Vec<String> = Vec::new();
+        let mut allowed_urls: Vec<Pattern> = Vec::new();
+        let mut allowed_bids: Vec<Pattern> = Vec::new();
+        let mut blog_url_template: Option<String> = None;
         let mut user_agent: Result<String, Self::Error> = Err("user_agent not specified!".into());
 
         let mut on_comment_cmds: Vec<String> = Vec::new();
 
         let mut admins: Vec<String> = Vec::new();
+        let mut moderators: Vec<String> = Vec::new();
 
         let mut login_timeout_minutes: u64 = DEFAULT_LOGIN_TIMEOUT_MINUTES;
 
         let mut x_real_ip_enabled: bool = false;
 
+        let mut maintenance_interval_seconds: u64 = DEFAULT_MAINTENANCE_INTERVAL_SECONDS;
+
+        let mut hide_deleted_comments: bool = false;
+        let mut deleted_retention_days: u64 = DEFAULT_DELETED_RETENTION_DAYS;
+
+        let mut public_revision_history: bool = false;
+
+        let mut timestamp_migration_offset: Option<String> = None;
+
+        let mut auto_close_days: u64 = 0;
+        let mut auto_close_from: AutoCloseFrom = AutoCloseFrom::default();
+
+        let mut max_reply_depth: u64 = DEFAULT_MAX_REPLY_DEPTH;
+
+        let mut comment_counts_max_age_seconds: u64 = DEFAULT_COMMENT_COUNTS_MAX_AGE_SECONDS;
+
+        let mut comments_cache_control: String = DEFAULT_COMMENTS_CACHE_CONTROL.to_owned();
+        let mut permalink_fragment: String = DEFAULT_PERMALINK_FRAGMENT.to_owned();
+
+        let mut comment_cache_size: usize = DEFAULT_COMMENT_CACHE_SIZE;
+        let mut comment_cache_ttl_seconds: u64 = DEFAULT_COMMENT_CACHE_TTL_SECONDS;
+
+        let mut markdown_extensions: Vec<MarkdownExtension> = DEFAULT_MARKDOWN_EXTENSIONS.to_vec();
+
+        let mut comment_limits: CommentLimits = CommentLimits::default();
+        let mut max_body_size: usize = DEFAULT_MAX_BODY_SIZE;
+
+        let mut check_confusable_names: bool = false;
+
+        let mut events_heartbeat_seconds: u64 = DEFAULT_EVENTS_HEARTBEAT_SECONDS;
+        let mut events_relay: bool = false;
+        let mut events_relay_interval_ms: u64 = DEFAULT_EVENTS_RELAY_INTERVAL_MS;
+
+        let mut public_search: bool = false;
+
+        let mut reactions: Vec<String> = DEFAULT_REACTIONS
+            .iter()
+            .map(|emoji| emoji.to_string())
+            .collect();
+
         let mut key: String = String::new();
         let mut val: String = String::new();
         let mut is_parsing_key = true;
-        for byte in file_buffered.bytes() {
-            let c: char = byte?.into();
+        for c in contents.chars() {
             if c == '\r' {
                 continue;
             }
@@ -183,9 +488,11 @@ impl TryFrom<&Path> for Config {
                 } else if key == "base_url" {
                     base_url = Ok(val);
                 } else if key == "allowed_url" {
-                    allowed_urls.push(val);
+                    allowed_urls.push(Pattern::parse(&val)?);
                 } else if key == "allowed_bid" {
-                    allowed_bids.push(val);
+                    allowed_bids.push(Pattern::parse(&val)?);
+                } else if key == "blog_url_template" {
+                    blog_url_template = Some(val);
                 } else if key == "user_agent" {
                     user_agent = Ok(val);
                 } else if key == "on_comment_cmd" {
@@ -196,6 +503,62 @@ impl TryFrom<&Path> for Config {
                     login_timeout_minutes = val.parse()?;
                 } else if key == "x_real_ip_enabled" {
                     x_real_ip_enabled = val.parse()?;
+                } else if key == "maintenance_interval" {
+                    maintenance_interval_seconds = val.parse()?;
+                } else if key == "hide_deleted_comments" {
+                    hide_deleted_comments = val.parse()?;
+                } else if key == "deleted_retention_days" {
+                    deleted_retention_days = val.parse()?;
+                } else if key == "public_revision_history" {
+                    public_revision_history = val.parse()?;
+                } else if key == "timestamp_migration_offset" {
+                    timestamp_migration_offset = Some(parse_utc_offset(&val)?);
+                } else if key == "auto_close_days" {
+                    auto_close_days = val.parse()?;
+                } else if key == "auto_close_from" {
+                    auto_close_from = AutoCloseFrom::parse(&val)?;
+                } else if key == "max_reply_depth" {
+                    max_reply_depth = val.parse()?;
+                } else if key == "comment_counts_max_age" {
+                    comment_counts_max_age_seconds = val.parse()?;
+                } else if key == "comments_cache_control" {
+                    comments_cache_control = val.to_owned();
+                } else if key == "comment_cache_size" {
+                    comment_cache_size = val.parse()?;
+                } else if key == "comment_cache_ttl" {
+                    comment_cache_ttl_seconds = val.parse()?;
+                } else if key == "markdown_extensions" {
+                    markdown_extensions = MarkdownExtension::parse_list(&val)?;
+                } else if key == "min_comment_chars" {
+                    comment_limits.min_chars = val.parse()?;
+                } else if key == "max_comment_chars" {
+                    comment_limits.max_chars = val.parse()?;
+                } else if key == "min_comment_bytes" {
+                    comment_limits.min_bytes = val.parse()?;
+                } else if key == "max_comment_bytes" {
+                    comment_limits.max_bytes = val.parse()?;
+                } else if key == "max_comment_lines" {
+                    comment_limits.max_lines = val.parse()?;
+                } else if key == "max_comment_links" {
+                    comment_limits.max_links = val.parse()?;
+                } else if key == "max_body_size" {
+                    max_body_size = val.parse()?;
+                } else if key == "check_confusable_names" {
+                    check_confusable_names = val.parse()?;
+                } else if key == "reactions" {
+                    reactions = parse_reactions(&val)?;
+                } else if key == "moderator" {
+                    moderators.push(val);
+                } else if key == "permalink_fragment" {
+                    permalink_fragment = val.to_owned();
+                } else if key == "events_heartbeat_seconds" {
+                    events_heartbeat_seconds = val.parse()?;
+                } else if key == "events_relay" {
+                    events_relay = val.parse()?;
+                } else if key == "events_relay_interval_ms" {
+                    events_relay_interval_ms = val.parse()?;
+                } else if key == "public_search" {
+                    public_search = val.parse()?;
                 } else {
                     println!("WARNING: Got unknown config key \"{}\"!", key);
                 }
@@ -228,9 +591,11 @@ impl TryFrom<&Path> for Config {
             } else if key == "base_url" {
                 base_url = Ok(val);
             } else if key == "allowed_url" {
-                allowed_urls.push(val);
+                allowed_urls.push(Pattern::parse(&val)?);
             } else if key == "allowed_bid" {
-                allowed_bids.push(val);
+                allowed_bids.push(Pattern::parse(&val)?);
+            } else if key == "blog_url_template" {
+                blog_url_template = Some(val);
             } else if key == "user_agent" {
                 user_agent = Ok(val);
             } else if key == "on_comment_cmd" {
@@ -241,11 +606,85 @@ impl TryFrom<&Path> for Config {
                 login_timeout_minutes = val.parse()?;
             } else if key == "x_real_ip_enabled" {
                 x_real_ip_enabled = val.parse()?;
+            } else if key == "maintenance_interval" {
+                maintenance_interval_seconds = val.parse()?;
+            } else if key == "hide_deleted_comments" {
+                hide_deleted_comments = val.parse()?;
+            } else if key == "deleted_retention_days" {
+                deleted_retention_days = val.parse()?;
+            } else if key == "public_revision_history" {
+                public_revision_history = val.parse()?;
+            } else if key == "timestamp_migration_offset" {
+                timestamp_migration_offset = Some(parse_utc_offset(&val)?);
+            } else if key == "auto_close_days" {
+                auto_close_days = val.parse()?;
+            } else if key == "auto_close_from" {
+                auto_close_from = AutoCloseFrom::parse(&val)?;
+            } else if key == "max_reply_depth" {
+                max_reply_depth = val.parse()?;
+            } else if key == "comment_counts_max_age" {
+                comment_counts_max_age_seconds = val.parse()?;
+            } else if key == "comments_cache_control" {
+                comments_cache_control = val.to_owned();
+            } else if key == "comment_cache_size" {
+                comment_cache_size = val.parse()?;
+            } else if key == "comment_cache_ttl" {
+                comment_cache_ttl_seconds = val.parse()?;
+            } else if key == "markdown_extensions" {
+                markdown_extensions = MarkdownExtension::parse_list(&val)?;
+            } else if key == "min_comment_chars" {
+                comment_limits.min_chars = val.parse()?;
+            } else if key == "max_comment_chars" {
+                comment_limits.max_chars = val.parse()?;
+            } else if key == "min_comment_bytes" {
+                comment_limits.min_bytes = val.parse()?;
+            } else if key == "max_comment_bytes" {
+                comment_limits.max_bytes = val.parse()?;
+            } else if key == "max_comment_lines" {
+                comment_limits.max_lines = val.parse()?;
+            } else if key == "max_comment_links" {
+                comment_limits.max_links = val.parse()?;
+            } else if key == "max_body_size" {
+                max_body_size = val.parse()?;
+            } else if key == "check_confusable_names" {
+                check_confusable_names = val.parse()?;
+            } else if key == "reactions" {
+                reactions = parse_reactions(&val)?;
+            } else if key == "moderator" {
+                moderators.push(val);
+            } else if key == "permalink_fragment" {
+                permalink_fragment = val.to_owned();
+            } else if key == "events_heartbeat_seconds" {
+                events_heartbeat_seconds = val.parse()?;
+            } else if key == "events_relay" {
+                events_relay = val.parse()?;
+            } else if key == "events_relay_interval_ms" {
+                events_relay_interval_ms = val.parse()?;
+            } else if key == "public_search" {
+                public_search = val.parse()?;
             } else {
                 println!("WARNING: Got unknown config key \"{}\"!", key);
             }
         }
 
+        if maintenance_interval_seconds == 0 {
+            return Err("maintenance_interval must be greater than 0!".into());
+        }
+        if events_heartbeat_seconds == 0 {
+            return Err("events_heartbeat_seconds must be greater than 0!".into());
+        }
+        if events_relay_interval_ms == 0 {
+            return Err("events_relay_interval_ms must be greater than 0!".into());
+        }
+
+        if comment_limits.max_bytes > MAX_COMMENT_BYTES {
+            return Err(format!("max_comment_bytes must be at most {}!", MAX_COMMENT_BYTES).into());
+        } else if comment_limits.min_chars > comment_limits.max_chars {
+            return Err("min_comment_chars must not be greater than max_comment_chars!".into());
+        } else if comment_limits.min_bytes > comment_limits.max_bytes {
+            return Err("min_comment_bytes must not be greater than max_comment_bytes!".into());
+        }
+
         Ok(Config {
             sql_user: sql_user?,
             sql_pass: sql_pass?,
@@ -259,11 +698,35 @@ impl TryFrom<&Path> for Config {
             base_url: base_url?,
             allowed_urls,
             allowed_bids,
+            blog_url_template,
             user_agent: user_agent?,
             on_comment_cmds,
             admins,
+            moderators,
             login_timeout: login_timeout_minutes,
             x_real_ip_enabled,
+            maintenance_interval: maintenance_interval_seconds,
+            hide_deleted_comments,
+            deleted_retention_days,
+            public_revision_history,
+            timestamp_migration_offset,
+            auto_close_days,
+            auto_close_from,
+            max_reply_depth,
+            comment_counts_max_age: comment_counts_max_age_seconds,
+            comments_cache_control,
+            permalink_fragment,
+            comment_cache_size,
+            comment_cache_ttl: comment_cache_ttl_seconds,
+            markdown_extensions,
+            comment_limits,
+            max_body_size,
+            check_confusable_names,
+            reactions,
+            events_heartbeat_seconds,
+            events_relay,
+            events_relay_interval_ms,
+            public_search,
         })
     }
 }
@@ -17,34 +17,133 @@
 use std::{num::ParseIntError, str::ParseBoolError};
 
 use reqwest::StatusCode;
-use salvo::{Depot, Request, Response, Writer, async_trait};
+use salvo::{Depot, Request, Response, Writer, async_trait, writing::Json};
+use serde::Serialize;
+
+// Set in the depot to always render errors as JSON.
+pub const JSON_ERRORS_KEY: &str = "json_errors";
+
+// The codes are part of the API, don't rename them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadRequest,
+    NotLoggedIn,
+    SessionExpired,
+    NotAdmin,
+    NotOwner,
+    BlogClosed,
+    BlogNotAllowed,
+    UrlNotAllowed,
+    InvalidReply,
+    InvalidReaction,
+    CommentNotFound,
+    BlogNotFound,
+    StateExpired,
+    CommentNotDeleted,
+    CommentEmpty,
+    CommentTooShort,
+    CommentTooLong,
+    TooManyLines,
+    TooManyLinks,
+    BodyTooLarge,
+    Internal,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::NotLoggedIn => "not_logged_in",
+            ErrorCode::SessionExpired => "session_expired",
+            ErrorCode::NotAdmin => "not_admin",
+            ErrorCode::NotOwner => "not_owner",
+            ErrorCode::BlogClosed => "blog_closed",
+            ErrorCode::BlogNotAllowed => "blog_not_allowed",
+            ErrorCode::UrlNotAllowed => "url_not_allowed",
+            ErrorCode::InvalidReply => "invalid_reply",
+            ErrorCode::InvalidReaction => "invalid_reaction",
+            ErrorCode::CommentNotFound => "comment_not_found",
+            ErrorCode::BlogNotFound => "blog_not_found",
+            ErrorCode::StateExpired => "state_expired",
+            ErrorCode::CommentNotDeleted => "comment_not_deleted",
+            ErrorCode::CommentEmpty => "comment_empty",
+            ErrorCode::CommentTooShort => "comment_too_short",
+            ErrorCode::CommentTooLong => "comment_too_long",
+            ErrorCode::TooManyLines => "too_many_lines",
+            ErrorCode::TooManyLinks => "too_many_links",
+            ErrorCode::BodyTooLarge => "body_too_large",
+            ErrorCode::Internal => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest
+            | ErrorCode::InvalidReply
+            | ErrorCode::InvalidReaction
+            | ErrorCode::CommentEmpty
+            | ErrorCode::CommentTooShort
+            | ErrorCode::TooManyLines
+            | ErrorCode::TooManyLinks => StatusCode::BAD_REQUEST,
+            ErrorCode::NotLoggedIn | ErrorCode::SessionExpired => StatusCode::UNAUTHORIZED,
+            ErrorCode::NotAdmin
+            | ErrorCode::NotOwner
+            | ErrorCode::BlogClosed
+            | ErrorCode::BlogNotAllowed
+            | ErrorCode::UrlNotAllowed => StatusCode::FORBIDDEN,
+            ErrorCode::CommentNotFound | ErrorCode::BlogNotFound => StatusCode::NOT_FOUND,
+            ErrorCode::StateExpired | ErrorCode::CommentNotDeleted => StatusCode::CONFLICT,
+            ErrorCode::CommentTooLong | ErrorCode::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Error {
     StrUtf8(std::str::Utf8Error),
     TimeFormat(time::error::Format),
     TimeInvalFormat(time::error::InvalidFormatDescription),
-    TimeIndetOffset(time::error::IndeterminateOffset),
+    TimeParse(time::error::Parse),
     SerdeJson(serde_json::Error),
     SalvoHttpParse(salvo::http::ParseError),
     Reqwest(reqwest::Error),
     ParseInt(ParseIntError),
     ParseBool(ParseBoolError),
     IO(std::io::Error),
+    Regex(regex::Error),
+    Row(crate::from_row::RowError),
     Generic(String),
     ClientErr(Box<Error>),
+    Coded(ErrorCode, String),
 }
 
 impl Error {
+    pub fn coded(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error::Coded(code, message.into())
+    }
+
+    // Coded errors are already client errors, and keep their code.
     pub fn into_client_err(self) -> Self {
-        Error::ClientErr(Box::new(self))
+        match self {
+            Error::Coded(..) => self,
+            _ => Error::ClientErr(Box::new(self)),
+        }
     }
 
     pub fn err_to_client_err<T>(error: T) -> Self
     where
         T: Into<Error>,
     {
-        Error::ClientErr(Box::new(error.into()))
+        error.into().into_client_err()
+    }
+
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Coded(code, _) => *code,
+            Error::ClientErr(_) => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
     }
 }
 
@@ -53,13 +152,16 @@ impl std::fmt::Display for Error {
         match self {
             Error::Generic(s) => f.write_str(s),
             Error::IO(error) => error.fmt(f),
+            Error::Regex(error) => error.fmt(f),
+            Error::Row(error) => error.fmt(f),
             Error::ParseInt(error) => error.fmt(f),
             Error::ParseBool(error) => error.fmt(f),
             Error::Reqwest(error) => error.fmt(f),
             Error::SalvoHttpParse(error) => error.fmt(f),
             Error::ClientErr(error) => error.fmt(f),
+            Error::Coded(_, message) => f.write_str(message),
             Error::SerdeJson(error) => error.fmt(f),
-            Error::TimeIndetOffset(error) => error.fmt(f),
+            Error::TimeParse(error) => error.fmt(f),
             Error::TimeInvalFormat(error) => error.fmt(f),
             Error::TimeFormat(error) => error.fmt(f),
             Error::StrUtf8(error) => error.fmt(f),
@@ -87,6 +189,18 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<regex::Error> for Error {
+    fn from(value: regex::Error) -> Self {
+        Error::Regex(value)
+    }
+}
+
+impl From<crate::from_row::RowError> for Error {
+    fn from(value: crate::from_row::RowError) -> Self {
+        Error::Row(value)
+    }
+}
+
 impl From<ParseIntError> for Error {
     fn from(value: ParseIntError) -> Self {
         Error::ParseInt(value)
@@ -117,9 +231,9 @@ impl From<serde_json::Error> for Error {
     }
 }
 
-impl From<time::error::IndeterminateOffset> for Error {
-    fn from(value: time::error::IndeterminateOffset) -> Self {
-        Error::TimeIndetOffset(value)
+impl From<time::error::Parse> for Error {
+    fn from(value: time::error::Parse) -> Self {
+        Error::TimeParse(value)
     }
 }
 
@@ -141,29 +255,63 @@ impl From<std::str::Utf8Error> for Error {
     }
 }
 
+#[derive(Serialize)]
+struct ErrorResponse {
+    code: &'static str,
+    message: String,
+}
+
+// JSON is used if the client accepts it before (or without) HTML.
+fn wants_json(req: &Request, depot: &Depot) -> bool {
+    if depot.get::<bool>(JSON_ERRORS_KEY).is_ok() {
+        return true;
+    }
+
+    let accept: String = req.header("accept").unwrap_or_default();
+    match (accept.find("application/json"), accept.find("text/html")) {
+        (Some(json_idx), Some(html_idx)) => json_idx < html_idx,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
 #[async_trait]
 impl Writer for Error {
-    async fn write(self, _req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+    async fn write(self, req: &mut Request, depot: &mut Depot, res: &mut Response) {
         eprintln!("{:?}", &self);
-        match &self {
-            Error::ClientErr(_error) => {
-                res.status_code(StatusCode::BAD_REQUEST);
-                res.body(format!(
-                    r#"<html><head><style>{}</style></head><body>
-                    <b>Bad Request</b>
-                    </body></html>"#,
-                    crate::COMMON_CSS,
-                ));
-            }
-            _ => {
-                res.status_code(StatusCode::INTERNAL_SERVER_ERROR);
-                res.body(format!(
-                    r#"<html><head><style>{}</style></head><body>
-                    <b>Internal Server Error</b>
-                    </body></html>"#,
-                    crate::COMMON_CSS,
-                ));
-            }
+        let code: ErrorCode = self.code();
+        // Only coded errors have messages meant for the client.
+        let message: Option<String> = match &self {
+            Error::Coded(_, message) => Some(message.clone()),
+            _ => None,
+        };
+        let status: StatusCode = code.status();
+        let reason: &str = status.canonical_reason().unwrap_or("Error");
+
+        res.status_code(status);
+        if wants_json(req, depot) {
+            res.render(Json(ErrorResponse {
+                code: code.as_str(),
+                message: message.unwrap_or_else(|| reason.to_owned()),
+            }));
+        } else if let Some(message) = message {
+            res.body(format!(
+                r#"<html><head><style>{}</style></head><body>
+                <b>{}</b><br>
+                <p>{}</p>
+                </body></html>"#,
+                crate::COMMON_CSS,
+                reason,
+                message,
+            ));
+        } else {
+            res.body(format!(
+                r#"<html><head><style>{}</style></head><body>
+                <b>{}</b>
+                </body></html>"#,
+                crate::COMMON_CSS,
+                reason,
+            ));
         }
     }
 }
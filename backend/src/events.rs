@@ -0,0 +1,339 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use salvo::{
+    Response,
+    sse::{SseEvent, SseKeepAlive},
+};
+use serde::Serialize;
+use tokio::sync::{broadcast, watch};
+
+use crate::{
+    Config,
+    error::Error,
+    sql::{self, SQLCtx},
+};
+
+// Events kept for clients resuming with "Last-Event-ID".
+const HISTORY_SIZE: usize = 1000;
+const CHANNEL_CAPACITY: usize = 256;
+// Ids below the last relayed one that are read again, as an event can commit
+// after one with a higher id.
+const RELAY_WINDOW: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventKind {
+    Created,
+    Edited,
+    Deleted,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::Created => "created",
+            EventKind::Edited => "edited",
+            EventKind::Deleted => "deleted",
+        }
+    }
+
+    pub fn parse(val: &str) -> Option<Self> {
+        match val {
+            "created" => Some(EventKind::Created),
+            "edited" => Some(EventKind::Edited),
+            "deleted" => Some(EventKind::Deleted),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    #[serde(skip)]
+    pub id: u64,
+    #[serde(skip)]
+    pub kind: EventKind,
+    pub blog_id: String,
+    pub comment_id: String,
+}
+
+struct History {
+    events: VecDeque<Arc<Event>>,
+    // The highest id sent.
+    last_id: u64,
+    // Every event after this id is still in "events".
+    kept_after_id: u64,
+    // The ids in the relay's window that were already sent.
+    relayed: BTreeSet<u64>,
+}
+
+// Fans out comment events to the clients of every blog_id. With
+// "events_relay", events are published through the db and sent by
+// "run_relay", so that every instance sends them with the same ids.
+pub struct EventHub {
+    sender: broadcast::Sender<Arc<Event>>,
+    history: Mutex<History>,
+    relay: bool,
+    stop: watch::Receiver<bool>,
+}
+
+impl EventHub {
+    // Without the relay, ids start from the current time so that they keep
+    // increasing across restarts.
+    pub fn new(config: &Config, stop: watch::Receiver<bool>) -> Result<Self, Error> {
+        let mut relayed: BTreeSet<u64> = BTreeSet::new();
+        let last_id: u64 = if config.events_relay {
+            // Events from before the start are not sent.
+            let last_id: u64 = sql::get_last_comment_event_id(config.into())?;
+            for event in
+                sql::get_comment_events_after(config.into(), last_id.saturating_sub(RELAY_WINDOW))?
+            {
+                relayed.insert(event.id);
+            }
+            relayed.last().copied().unwrap_or_default().max(last_id)
+        } else {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_millis() as u64)
+                .unwrap_or_default()
+        };
+
+        Ok(EventHub {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            history: Mutex::new(History {
+                events: VecDeque::new(),
+                last_id,
+                kept_after_id: last_id,
+                relayed,
+            }),
+            relay: config.events_relay,
+            stop,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, History> {
+        // The history is valid even if a holder panicked.
+        self.history
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Should be called after the change is committed. Failing to publish only
+    // means clients miss the event, so errors are just logged.
+    pub fn publish(&self, sql_ctx: SQLCtx, blog_id: &str, kind: EventKind, comment_id: &str) {
+        if self.relay {
+            if let Err(e) = sql::add_comment_event(sql_ctx, blog_id, kind.as_str(), comment_id) {
+                eprintln!("Failed to publish comment event: {}", e);
+            }
+        } else {
+            let mut history = self.lock();
+            let id: u64 = history.last_id + 1;
+            self.send(
+                &mut history,
+                Event {
+                    id,
+                    kind,
+                    blog_id: blog_id.to_owned(),
+                    comment_id: comment_id.to_owned(),
+                },
+            );
+        }
+    }
+
+    // Sending while holding the lock keeps the history and the channel in the
+    // same order for "subscribe".
+    fn send(&self, history: &mut History, event: Event) {
+        history.last_id = history.last_id.max(event.id);
+        let event = Arc::new(event);
+        if history.events.len() == HISTORY_SIZE
+            && let Some(evicted) = history.events.pop_front()
+        {
+            history.kept_after_id = evicted.id;
+        }
+        history.events.push_back(event.clone());
+        // Fails only if no client is listening.
+        let _ = self.sender.send(event);
+    }
+
+    // Returns the events sent after "last_event_id" to resend, or None if some
+    // of them are no longer kept. Relayed events can be sent after one with a
+    // higher id, so those after "last_event_id" in the history are used if it
+    // is still kept.
+    fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> (Option<Vec<Arc<Event>>>, broadcast::Receiver<Arc<Event>>) {
+        let history = self.lock();
+        let receiver = self.sender.subscribe();
+
+        let missed: Option<Vec<Arc<Event>>> = match last_event_id {
+            None => Some(Vec::new()),
+            Some(id) if id < history.kept_after_id || id > history.last_id => None,
+            Some(id) => match history.events.iter().position(|event| event.id == id) {
+                Some(idx) => Some(history.events.iter().skip(idx + 1).cloned().collect()),
+                None => Some(
+                    history
+                        .events
+                        .iter()
+                        .filter(|event| event.id > id)
+                        .cloned()
+                        .collect(),
+                ),
+            },
+        };
+
+        (missed, receiver)
+    }
+}
+
+fn to_sse(event: &Event) -> SseEvent {
+    SseEvent::default()
+        .name(event.kind.as_str())
+        .id(event.id.to_string())
+        .text(serde_json::to_string(event).unwrap_or_default())
+}
+
+// Sent when events were missed, so the client has to fetch the comments again.
+fn reload_event() -> SseEvent {
+    SseEvent::default().name("reload").text("{}")
+}
+
+struct Subscription {
+    blog_id: String,
+    pending: VecDeque<SseEvent>,
+    receiver: broadcast::Receiver<Arc<Event>>,
+    stop: watch::Receiver<bool>,
+}
+
+// Streams the events of "blog_id" until the client disconnects or the server
+// stops.
+pub fn stream(
+    hub: &EventHub,
+    blog_id: String,
+    last_event_id: Option<u64>,
+    heartbeat: Duration,
+    res: &mut Response,
+) {
+    let (missed, receiver) = hub.subscribe(last_event_id);
+    let pending: VecDeque<SseEvent> = match missed {
+        Some(missed) => missed
+            .iter()
+            .filter(|event| event.blog_id == blog_id)
+            .map(|event| to_sse(event))
+            .collect(),
+        None => VecDeque::from([reload_event()]),
+    };
+
+    let subscription = Subscription {
+        blog_id,
+        pending,
+        receiver,
+        stop: hub.stop.clone(),
+    };
+
+    let events = futures_util::stream::unfold(subscription, |mut subscription| async move {
+        if let Some(event) = subscription.pending.pop_front() {
+            return Some((Ok::<_, Infallible>(event), subscription));
+        }
+
+        loop {
+            tokio::select! {
+                received = subscription.receiver.recv() => match received {
+                    Ok(event) if event.blog_id == subscription.blog_id => {
+                        return Some((Ok(to_sse(&event)), subscription));
+                    }
+                    Ok(_) => (),
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        return Some((Ok(reload_event()), subscription));
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                },
+                _ = subscription.stop.changed() => return None,
+            }
+        }
+    });
+
+    SseKeepAlive::new(events)
+        .max_interval(heartbeat)
+        .stream(res);
+}
+
+// Sends the events published through the db by every instance.
+pub async fn run_relay(config: Config, hub: Arc<EventHub>, mut stop: watch::Receiver<bool>) {
+    let mut interval =
+        tokio::time::interval(Duration::from_millis(config.events_relay_interval_ms));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let config = config.clone();
+                let hub = hub.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || relay_once(&config, &hub)).await {
+                    eprintln!("Event relay: Task panicked: {}", e);
+                }
+            }
+            _ = stop.changed() => {
+                break;
+            }
+        }
+    }
+
+    println!("Event relay: Stopped.");
+}
+
+fn relay_once(config: &Config, hub: &EventHub) {
+    let after_id: u64 = hub.lock().last_id.saturating_sub(RELAY_WINDOW);
+
+    let events = match sql::get_comment_events_after(config.into(), after_id) {
+        Ok(events) => events,
+        Err(e) => {
+            eprintln!("Event relay: Failed to get events: {}", e);
+            return;
+        }
+    };
+
+    let mut history = hub.lock();
+    for event in events {
+        if !history.relayed.insert(event.id) {
+            continue;
+        }
+        let Some(kind) = EventKind::parse(&event.kind) else {
+            eprintln!("Event relay: Unknown event kind \"{}\"!", event.kind);
+            continue;
+        };
+        hub.send(
+            &mut history,
+            Event {
+                id: event.id,
+                kind,
+                blog_id: event.blog_id,
+                comment_id: event.comment_id,
+            },
+        );
+    }
+
+    // Ids below the window are never read again.
+    let window_start: u64 = history.last_id.saturating_sub(RELAY_WINDOW);
+    history.relayed = history.relayed.split_off(&(window_start + 1));
+}
@@ -0,0 +1,228 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use msql_ffi::MSQLValueEnum;
+use time::{
+    OffsetDateTime, PrimitiveDateTime, format_description::BorrowedFormatItem,
+    macros::format_description,
+};
+
+use crate::error::Error;
+
+pub const DB_DATETIME_FORMAT: &[BorrowedFormatItem<'static>] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+
+static BAD_ROW_COUNT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowError {
+    ColumnCount {
+        row_type: &'static str,
+        expected: usize,
+        got: usize,
+    },
+    Column {
+        row_type: &'static str,
+        column: &'static str,
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for RowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowError::ColumnCount {
+                row_type,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{}: Expected {} columns from db, got {}",
+                row_type, expected, got
+            ),
+            RowError::Column {
+                row_type,
+                column,
+                reason,
+            } => write!(
+                f,
+                "{}: Invalid \"{}\" from db: {}",
+                row_type, column, reason
+            ),
+        }
+    }
+}
+
+fn parse_db_datetime(s: &str) -> Result<OffsetDateTime, Error> {
+    Ok(PrimitiveDateTime::parse(s, DB_DATETIME_FORMAT)?.assume_utc())
+}
+
+fn value_kind(value: &MSQLValueEnum) -> &'static str {
+    match value {
+        MSQLValueEnum::Error => "error",
+        MSQLValueEnum::Null => "null",
+        MSQLValueEnum::Int64(_) => "int64",
+        MSQLValueEnum::UInt64(_) => "uint64",
+        MSQLValueEnum::String(_) => "string",
+        MSQLValueEnum::DoubleF64(_) => "double",
+    }
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String>;
+}
+
+impl FromValue for String {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::String(s) => Ok(s.to_owned()),
+            _ => Err(format!("expected string, got {}", value_kind(value))),
+        }
+    }
+}
+
+impl FromValue for u64 {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::UInt64(u) => Ok(*u),
+            MSQLValueEnum::Int64(i) => {
+                u64::try_from(*i).map_err(|_| format!("expected unsigned integer, got {}", i))
+            }
+            _ => Err(format!("expected integer, got {}", value_kind(value))),
+        }
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::Int64(i) => Ok(*i),
+            MSQLValueEnum::UInt64(u) => {
+                i64::try_from(*u).map_err(|_| format!("integer {} is out of range", u))
+            }
+            _ => Err(format!("expected integer, got {}", value_kind(value))),
+        }
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::Int64(i) => Ok(*i != 0),
+            MSQLValueEnum::UInt64(u) => Ok(*u != 0),
+            _ => Err(format!("expected boolean, got {}", value_kind(value))),
+        }
+    }
+}
+
+impl FromValue for OffsetDateTime {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::String(s) => parse_db_datetime(s).map_err(|e| e.to_string()),
+            _ => Err(format!("expected datetime, got {}", value_kind(value))),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &MSQLValueEnum) -> Result<Self, String> {
+        match value {
+            MSQLValueEnum::Null => Ok(None),
+            _ => T::from_value(value).map(Some),
+        }
+    }
+}
+
+pub trait FromRow: Sized {
+    fn from_row(row: &[MSQLValueEnum]) -> Result<Self, Error>;
+}
+
+pub fn get_column<T: FromValue>(
+    row: &[MSQLValueEnum],
+    idx: usize,
+    row_type: &'static str,
+    column: &'static str,
+) -> Result<T, Error> {
+    let value = row.get(idx).ok_or_else(|| RowError::Column {
+        row_type,
+        column,
+        reason: "missing".to_owned(),
+    })?;
+
+    T::from_value(value).map_err(|reason| {
+        RowError::Column {
+            row_type,
+            column,
+            reason,
+        }
+        .into()
+    })
+}
+
+// Rows that fail to decode in a listing are skipped instead of failing the
+// whole request, but they are still logged and counted.
+pub fn report_bad_row(error: &Error) {
+    let count = BAD_ROW_COUNT.fetch_add(1, Ordering::Relaxed) + 1;
+    eprintln!("Skipped bad row from db ({} total): {}", count, error);
+}
+
+// Declares a struct and implements FromRow for it. Fields are decoded in
+// declaration order, so they must match the order of the selected columns.
+macro_rules! db_row {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($field_vis:vis $field:ident: $field_type:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($field_vis $field: $field_type),*
+        }
+
+        impl $crate::from_row::FromRow for $name {
+            fn from_row(row: &[msql_ffi::MSQLValueEnum]) -> Result<Self, $crate::error::Error> {
+                const COLUMNS: &[&str] = &[$(stringify!($field)),*];
+                if row.len() != COLUMNS.len() {
+                    return Err($crate::from_row::RowError::ColumnCount {
+                        row_type: stringify!($name),
+                        expected: COLUMNS.len(),
+                        got: row.len(),
+                    }
+                    .into());
+                }
+
+                let mut _idx: usize = 0;
+                Ok($name {
+                    $($field: {
+                        let value = $crate::from_row::get_column(
+                            row,
+                            _idx,
+                            stringify!($name),
+                            stringify!($field),
+                        )?;
+                        _idx += 1;
+                        value
+                    }),*
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use db_row;
@@ -0,0 +1,119 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use salvo::{Request, Response, http::StatusCode};
+use time::{
+    OffsetDateTime, PrimitiveDateTime, format_description::BorrowedFormatItem,
+    macros::format_description,
+};
+
+use crate::error::Error;
+
+// The IMF-fixdate format, e.g. "Sun, 06 Nov 1994 08:49:37 GMT".
+const HTTP_DATE_FORMAT: &[BorrowedFormatItem<'static>] = format_description!(
+    "[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT"
+);
+
+// A weak ETag, since the same comments always make the same body but the
+// body may be compressed by a proxy.
+pub fn make_etag<T: Hash>(version: &T) -> String {
+    let mut hasher = DefaultHasher::new();
+    version.hash(&mut hasher);
+    format!("W/\"{:016x}\"", hasher.finish())
+}
+
+// Responses that mark a logged in viewer's reactions must not be stored by
+// shared caches, so "public" and "s-maxage" are replaced with "private".
+pub fn viewer_cache_control(cache_control: &str, has_viewer: bool) -> String {
+    if !has_viewer {
+        return cache_control.to_owned();
+    }
+
+    std::iter::once("private")
+        .chain(cache_control.split(',').map(str::trim).filter(|directive| {
+            let name: String = directive
+                .split('=')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            !name.is_empty() && name != "public" && name != "private" && name != "s-maxage"
+        }))
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+fn format_http_date(date: OffsetDateTime) -> Result<String, Error> {
+    Ok(date
+        .to_offset(time::UtcOffset::UTC)
+        .format(HTTP_DATE_FORMAT)?)
+}
+
+fn parse_http_date(val: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(val.trim(), HTTP_DATE_FORMAT)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    // Weak comparison, so "W/" prefixes are ignored.
+    let etag: &str = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Sets the caching headers, and if the client's copy is still current, sets
+// 304 and returns true. The caller must not write a body then.
+// If-Modified-Since is only used if If-None-Match isn't given.
+pub fn check_not_modified(
+    req: &Request,
+    res: &mut Response,
+    cache_control: &str,
+    etag: &str,
+    last_modified: Option<OffsetDateTime>,
+) -> Result<bool, Error> {
+    res.add_header("Cache-Control", cache_control, true)
+        .map_err(|_| Error::from("Failed to set Cache-Control header!"))?;
+    res.add_header("ETag", etag, true)
+        .map_err(|_| Error::from("Failed to set ETag header!"))?;
+    if let Some(last_modified) = last_modified {
+        res.add_header("Last-Modified", format_http_date(last_modified)?, true)
+            .map_err(|_| Error::from("Failed to set Last-Modified header!"))?;
+    }
+
+    let not_modified: bool = if let Some(if_none_match) = req.header::<String>("if-none-match") {
+        etag_matches(&if_none_match, etag)
+    } else if let Some(last_modified) = last_modified
+        && let Some(since) = req
+            .header::<String>("if-modified-since")
+            .and_then(|since| parse_http_date(&since))
+    {
+        // HTTP dates have no sub-second part.
+        last_modified.unix_timestamp() <= since.unix_timestamp()
+    } else {
+        false
+    };
+
+    if not_modified {
+        res.status_code(StatusCode::NOT_MODIFIED);
+    }
+
+    Ok(not_modified)
+}
@@ -14,20 +14,51 @@
 // OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
 // PERFORMANCE OF THIS SOFTWARE.
 
+mod allowlist;
+mod api_v1;
 mod arg_parse;
+mod comment_cache;
 mod config;
 mod error;
+mod events;
+mod from_row;
+mod http_cache;
+mod maintenance;
+mod markdown;
+mod pagination;
+mod search;
 mod signal;
 mod sql;
+mod thread;
+mod unicode;
 
-use std::time::Duration;
+use std::{
+    collections::HashSet,
+    sync::{Arc, LazyLock},
+    time::Duration,
+};
 
-use error::Error;
+use error::{Error, ErrorCode};
+use regex::Regex;
 use reqwest::Url;
-use salvo::prelude::*;
+use salvo::{Writer, prelude::*};
+use serde::Serialize;
 use tokio::time::sleep;
 
-use crate::sql::SQLCtx;
+use crate::{
+    comment_cache::CommentCache,
+    events::{EventHub, EventKind},
+    markdown::Renderer,
+    sql::SQLCtx,
+};
+
+pub const MAX_COMMENT_COUNTS_BLOG_IDS: usize = 100;
+
+// Counts "https://www.example.com" once.
+static LINK_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)https?://(?:www\.)?|mailto:|\bwww\.").unwrap());
+
+const CLOSED_MESSAGE: &str = "Comments are closed for this blog post.";
 
 pub const COMMON_CSS: &str = r#"
     body {
@@ -66,9 +97,10 @@ pub const WRITE_COMMENT_PAGE: &str = r#"
             the grounds that it is spam/hateful/etc. Please use common sense,
             and please be courteous to others, even when contrary.</p>
             <p>You can edit/delete your comment after posting it.</p>
+            <p>Comments are formatted with Markdown.</p>
         </div><br>
         <img width="64" height="64" src="{USER_AVATAR_URL}" /> <b>{USER_NAME}</b> <a href="{USER_PROFILE}">(User Profile)</a><br>
-        <textarea id="comment_text" name="comment_text" rows="10" cols="50" autofocus=true maxlength="65000"></textarea><br>
+        <textarea id="comment_text" name="comment_text" rows="10" cols="50" autofocus=true maxlength="{MAX_COMMENT_CHARS}"></textarea><br>
         <button id="comment_submit_button">Submit</button><br>
         <p id="status_paragraph"></p>
         <script>
@@ -81,12 +113,18 @@ pub const WRITE_COMMENT_PAGE: &str = r#"
                         body: json,
                         headers: {
                             "Content-Type": "application/json",
+                            "Accept": "application/json",
                         },
                     }
                 );
                 if (!response.ok) {
                     let status_p = document.getElementById("status_paragraph");
-                    status_p.innerText = "ERROR: Failed to submit comment!";
+                    const error = await response.json().catch(() => null);
+                    if (error !== null) {
+                        status_p.innerText = "ERROR: " + error.message;
+                    } else {
+                        status_p.innerText = "ERROR: Failed to submit comment!";
+                    }
                     throw new Error(`Response status: ${response.status}`);
                 } else {
                     window.location = "{BLOG_URL}";
@@ -127,9 +165,10 @@ pub const EDIT_COMMENT_PAGE: &str = r#"
             the grounds that it is spam/hateful/etc. Please use common sense,
             and please be courteous to others, even when contrary.</p>
             <p>You can edit/delete your comment after editing it.</p>
+            <p>Comments are formatted with Markdown.</p>
         </div><br>
         <img width="64" height="64" src="{USER_AVATAR_URL}" /> <b>{USER_NAME}</b> <a href="{USER_PROFILE}">(User Profile)</a><br>
-        <textarea id="comment_text" name="comment_text" rows="10" cols="50" autofocus=true maxlength="65000">Loading...</textarea><br>
+        <textarea id="comment_text" name="comment_text" rows="10" cols="50" autofocus=true maxlength="{MAX_COMMENT_CHARS}">Loading...</textarea><br>
         <button id="comment_submit_button">Submit</button><br>
         <p id="status_paragraph"></p>
         <script>
@@ -151,12 +190,18 @@ pub const EDIT_COMMENT_PAGE: &str = r#"
                         body: json,
                         headers: {
                             "Content-Type": "application/json",
+                            "Accept": "application/json",
                         },
                     }
                 );
                 if (!response.ok) {
                     let status_p = document.getElementById("status_paragraph");
-                    status_p.innerText = "Error: Failed to edit comment!";
+                    const error = await response.json().catch(() => null);
+                    if (error !== null) {
+                        status_p.innerText = "Error: " + error.message;
+                    } else {
+                        status_p.innerText = "Error: Failed to edit comment!";
+                    }
                     throw new Error(`Response status: ${response.status}`);
                 } else {
                     window.location = "{BLOG_URL}";
@@ -173,6 +218,7 @@ pub const EDIT_COMMENT_PAGE: &str = r#"
                     let submit_obj = {};
                     submit_obj.comment_text = textarea.value;
                     submit_obj.comment_id = "{COMMENT_ID}";
+                    submit_obj.state = "{STATE_STRING}";
                     let submit_json = JSON.stringify(submit_obj);
                     submit_comment(submit_json);
                 });
@@ -194,13 +240,34 @@ pub struct Config {
     oauth_user: String,
     oauth_token: String,
     base_url: String,
-    allowed_urls: Vec<String>,
-    allowed_bids: Vec<String>,
+    allowed_urls: Vec<allowlist::Pattern>,
+    allowed_bids: Vec<allowlist::Pattern>,
+    blog_url_template: Option<String>,
     user_agent: String,
     on_comment_cmds: Vec<String>,
     admins: Vec<String>,
+    moderators: Vec<String>,
     login_timeout_minutes: u64,
     x_real_ip_enabled: bool,
+    maintenance_interval_seconds: u64,
+    hide_deleted_comments: bool,
+    deleted_retention_days: u64,
+    public_revision_history: bool,
+    timestamp_migration_offset: Option<String>,
+    auto_close_days: u64,
+    auto_close_from: config::AutoCloseFrom,
+    max_reply_depth: u64,
+    comment_counts_max_age_seconds: u64,
+    comments_cache_control: String,
+    permalink_fragment: String,
+    comment_limits: config::CommentLimits,
+    max_body_size: usize,
+    check_confusable_names: bool,
+    reactions: Vec<String>,
+    events_heartbeat_seconds: u64,
+    events_relay: bool,
+    events_relay_interval_ms: u64,
+    public_search: bool,
 }
 
 impl Config {
@@ -231,214 +298,227 @@ impl Config {
     pub fn get_x_real_ip_enabled(&self) -> bool {
         self.x_real_ip_enabled
     }
-}
-
-#[handler]
-async fn root_handler(res: &mut Response) {
-    res.body(format!(
-        "<html><head><style>{}</style></head><body><h1>Welcome</h1></body></html>",
-        COMMON_CSS
-    ));
-}
-
-#[handler]
-async fn comment_text_get(
-    req: &mut Request,
-    res: &mut Response,
-    depot: &mut Depot,
-) -> Result<(), Error> {
-    let config: &Config = depot.obtain().unwrap();
 
-    let comment_id: String = req
-        .try_query("comment_id")
-        .map_err(Error::err_to_client_err)?;
+    pub fn get_maintenance_interval(&self) -> u64 {
+        self.maintenance_interval_seconds
+    }
 
-    let comment_text: String = sql::get_comment_text(config.into(), &comment_id)?;
+    pub fn get_deleted_retention_days(&self) -> u64 {
+        self.deleted_retention_days
+    }
 
-    res.body(comment_text);
+    pub fn get_timestamp_migration_offset(&self) -> Option<&str> {
+        self.timestamp_migration_offset.as_deref()
+    }
+}
 
-    Ok(())
+fn json_get_str<'a>(json: &'a serde_json::Value, key: &str) -> Result<&'a str, Error> {
+    json.get(key)
+        .and_then(|val| val.as_str())
+        .ok_or(Error::from(format!("JSON parse error: \"{}\"", key)).into_client_err())
 }
 
-#[handler]
-async fn login_to_comment(
-    req: &mut Request,
-    res: &mut Response,
-    depot: &mut Depot,
-) -> Result<(), Error> {
-    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
-    let blog_url: String = req
-        .try_query("blog_url")
-        .map_err(Error::err_to_client_err)?;
-    let salvo_conf = depot.obtain::<Config>().unwrap();
-    let config: &Config = depot.obtain().unwrap();
-    let is_allowed_url: bool = salvo_conf.allowed_urls.iter().fold(false, |acc, val| {
-        if acc { acc } else { blog_url.starts_with(val) }
-    });
-    if !is_allowed_url {
-        eprintln!("Client blog_url is invalid! {}", blog_url);
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request</b>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
-    }
-    let is_allowed_bid: bool = salvo_conf
-        .allowed_bids
-        .iter()
-        .fold(false, |acc, val| if acc { acc } else { &blog_id == val });
-    if !is_allowed_bid {
-        eprintln!("Client blog id is invalid! {}", blog_id);
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request</b>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
+fn get_client_ip(req: &Request, config: &Config) -> String {
+    if config.get_x_real_ip_enabled()
+        && let Some(real_ip) = req.header("x-real-ip")
+    {
+        real_ip
+    } else {
+        req.remote_addr().to_string()
     }
+}
 
-    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+fn get_login(
+    req: &Request,
+    config: &Config,
+    sql_ctx: SQLCtx,
+    login_id: &str,
+) -> Result<Option<sql::LoginInfo>, Error> {
+    let client_ip: String = get_client_ip(req, config);
 
-    // Check if logged in.
-    sql::cleanup_logins(sql_ctx.clone(), config.login_timeout_minutes)?;
+    sql::check_logged_in(sql_ctx, login_id, &client_ip, config.get_login_timeout())
+}
 
-    let mut login: Option<sql::LoginInfo> = None;
-    let login_id: Result<String, _> = req.try_query("login_id");
-    if let Ok(login_id) = login_id {
-        if config.get_x_real_ip_enabled()
-            && let Some(real_ip) = req.header("x-real-ip")
-        {
-            login = sql::check_logged_in(sql_ctx.clone(), &login_id, real_ip)?;
-        } else {
-            login =
-                sql::check_logged_in(sql_ctx.clone(), &login_id, &req.remote_addr().to_string())?;
-        }
+// Gets the login of the optional "login_id" query parameter. Only used to mark
+// the viewer's reactions, so expired logins are ignored.
+fn get_viewer(
+    req: &Request,
+    config: &Config,
+    sql_ctx: SQLCtx,
+) -> Result<Option<sql::LoginInfo>, Error> {
+    match req.try_query::<String>("login_id").ok() {
+        Some(login_id) => get_login(req, config, sql_ctx, &login_id),
+        None => Ok(None),
     }
+}
 
-    if let Some(login) = login {
-        // Logged in.
-        let uuid = sql::create_rng_uuid(sql_ctx.clone(), None)?;
-        sql::add_pseudo_comment_data(
-            sql_ctx.clone(),
-            &uuid,
-            login.user_github_id,
-            &login.username,
-            &login.userurl,
-            &login.useravatar,
-            Some(&blog_id),
-            None,
-        )?;
+fn is_admin(config: &Config, userlogin: &str) -> bool {
+    config.admins.iter().any(|admin| admin == userlogin)
+}
 
-        res.body(
-            WRITE_COMMENT_PAGE
-                .replace("{BLOG_ID}", &blog_id)
-                .replace("{COMMON_CSS}", COMMON_CSS)
-                .replace("{USER_AVATAR_URL}", &login.useravatar)
-                .replace("{USER_NAME}", &login.username)
-                .replace("{USER_PROFILE}", &login.userurl)
-                .replace("{BASE_URL}", &config.base_url)
-                .replace(
-                    "{BLOG_URL}",
-                    &format!("{}#{}comment{}", &blog_url, &blog_id, &uuid),
-                )
-                .replace("{STATE_STRING}", &uuid)
-                .replace("{LOGIN_SETUP}", ""),
-        );
-        return Ok(());
+// Comments only store the commenter's profile url, which ends in their login.
+fn login_from_userurl(userurl: &str) -> Option<&str> {
+    userurl.strip_prefix("https://github.com/")
+}
+
+fn set_badges(config: &Config, comments: &mut [sql::Comment], author: Option<&str>) {
+    for comment in comments.iter_mut() {
+        let Some(userlogin) = login_from_userurl(&comment.userurl) else {
+            continue;
+        };
+        comment.admin = is_admin(config, userlogin);
+        comment.moderator = config.moderators.iter().any(|login| login == userlogin);
+        comment.author = author == Some(userlogin);
     }
+}
 
-    // Setup for Github auth.
-    let uuid = sql::create_rng_uuid(sql_ctx.clone(), None)?;
-    let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_make_comment", salvo_conf.base_url),
-        &[
-            ("blog_id", &blog_id),
-            (
-                "blog_url",
-                &format!("{}#{}comment{}", blog_url, &blog_id, &uuid),
-            ),
-        ],
-    )
-    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
-    let github_api_url = Url::parse_with_params(
-        "https://github.com/login/oauth/authorize",
-        &[
-            ("client_id", salvo_conf.oauth_user.as_str()),
-            ("state", uuid.as_str()),
-            ("redirect_uri", redirect_url.as_str()),
-        ],
-    )
-    .map_err(|_| Error::from("Failed to parse github api url!"))?;
-    let script = format!(
-        r#"
-            "use strict";
-            setTimeout(() => {{
-                window.location = "{}";
-            }}, 3000);
-        "#,
-        github_api_url.as_str()
+// Sets the badges and html of a blog's comments.
+fn prepare_comments(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    renderer: &Renderer,
+    blog_id: &str,
+    comments: &mut [sql::Comment],
+) -> Result<(), Error> {
+    let blog: Option<sql::Blog> = sql::get_blog(
+        sql_ctx,
+        blog_id,
+        config.auto_close_days,
+        config.auto_close_from,
+    )?;
+    set_badges(
+        config,
+        comments,
+        blog.as_ref().and_then(|blog| blog.author.as_deref()),
     );
+    renderer.render_comments(comments);
+    Ok(())
+}
 
-    res.body(format!(
-        r#"<html><head><style>{}</style></head><body>
-        <b>Redirecting to Github for Authentication...</b>
-        <script>
-        {}
-        </script>
-        </body></html>"#,
-        COMMON_CSS, script
-    ));
+// Fetches a blog's comments as they are cached.
+fn fetch_comments(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    renderer: &Renderer,
+    blog_id: &str,
+) -> Result<Vec<sql::Comment>, Error> {
+    let mut comments: Vec<sql::Comment> = sql::get_comments_per_blog_id(
+        sql_ctx.clone(),
+        blog_id,
+        config.hide_deleted_comments,
+        &config.reactions,
+    )?;
+    prepare_comments(config, sql_ctx, renderer, blog_id, &mut comments)?;
+    Ok(comments)
+}
 
-    Ok(())
+// Fetches a page of a blog's comments, see pagination::paginate(). Pages are
+// not cached.
+fn fetch_comment_page(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    renderer: &Renderer,
+    blog_id: &str,
+    order: pagination::Order,
+    limit: usize,
+    cursor: Option<&str>,
+) -> Result<(Vec<sql::Comment>, Option<String>, u64), Error> {
+    let (mut comments, next_cursor, total) =
+        pagination::paginate(config, sql_ctx.clone(), blog_id, order, limit, cursor)?;
+    prepare_comments(config, sql_ctx, renderer, blog_id, &mut comments)?;
+    Ok((comments, next_cursor, total))
 }
 
-#[handler]
-async fn github_auth_make_comment(
-    req: &mut Request,
-    res: &mut Response,
-    depot: &mut Depot,
-) -> Result<(), Error> {
-    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
-    let blog_url: String = req
-        .try_query("blog_url")
-        .map_err(Error::err_to_client_err)?;
-    let state: String = req.try_query("state").map_err(Error::err_to_client_err)?;
-    let code: String = req.try_query("code").map_err(Error::err_to_client_err)?;
+// Uses the blog's canonical url, or "blog_url_template" if it has none.
+fn make_permalink(
+    config: &Config,
+    blog: Option<&sql::Blog>,
+    blog_id: &str,
+    comment_id: &str,
+) -> Option<String> {
+    let url: String = blog.and_then(|blog| blog.url.clone()).or_else(|| {
+        config
+            .blog_url_template
+            .as_deref()
+            .map(|template| allowlist::canonical_url(template, blog_id))
+    })?;
+    let url: &str = url.split('#').next().unwrap_or_default();
+
+    Some(format!(
+        "{}#{}",
+        url,
+        config
+            .permalink_fragment
+            .replace("{blog_id}", blog_id)
+            .replace("{comment_id}", comment_id)
+    ))
+}
 
-    let config: &Config = depot.obtain().unwrap();
+// Only compares against the logins of admins, as their names aren't known.
+fn imitates_admin(config: &Config, name: &str, userlogin: &str) -> bool {
+    !is_admin(config, userlogin)
+        && config
+            .admins
+            .iter()
+            .any(|admin| unicode::is_confusable(name, admin))
+}
 
-    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+fn get_admin_login(
+    req: &Request,
+    config: &Config,
+    sql_ctx: SQLCtx,
+    login_id: &str,
+) -> Result<sql::LoginInfo, Error> {
+    let login = get_login(req, config, sql_ctx, login_id)?
+        .ok_or(Error::coded(ErrorCode::NotLoggedIn, "Not logged in!"))?;
 
-    let is_state_valid = sql::check_rng_uuid(sql_ctx.clone(), &state, None)?;
-    if !is_state_valid {
-        eprintln!("State is invalid (timed out?)!\n");
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request (took too long to verify)</b>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
+    if !is_admin(config, &login.userlogin) {
+        eprintln!(
+            "Non-admin tried to use an admin endpoint! {}",
+            &login.userlogin
+        );
+        return Err(Error::coded(ErrorCode::NotAdmin, "Not an admin!"));
     }
 
-    let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_make_comment", config.base_url),
-        &[
-            ("blog_id", &blog_id),
-            (
-                "blog_url",
-                &format!("{}#{}comment{}", blog_url, &blog_id, &state),
-            ),
-        ],
-    )
-    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
+    Ok(login)
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum CommentStatus {
+    Visible,
+    // Deleted by the commenter.
+    Deleted,
+    // Deleted by an admin.
+    Removed,
+}
+
+#[derive(Serialize)]
+struct CommentInfo {
+    blog_id: String,
+    permalink: Option<String>,
+    status: CommentStatus,
+    deleted_date: Option<String>,
+    deleted_date_ms: Option<i64>,
+    delete_reason: Option<String>,
+    #[serde(flatten)]
+    comment: sql::Comment,
+}
 
+struct GithubUser {
+    id: u64,
+    name: String,
+    login: String,
+    url: String,
+    avatar_url: String,
+}
+
+// "redirect_url" must be the same as the one given when authorizing.
+async fn fetch_github_user(
+    config: &Config,
+    code: &str,
+    redirect_url: &str,
+) -> Result<GithubUser, Error> {
     let client = reqwest::Client::builder();
     let client = client.user_agent(&config.user_agent).build()?;
     let g_res = client
@@ -446,8 +526,8 @@ async fn github_auth_make_comment(
         .query(&[
             ("client_id", config.oauth_user.as_str()),
             ("client_secret", config.oauth_token.as_str()),
-            ("code", code.as_str()),
-            ("redirect_uri", redirect_url.as_str()),
+            ("code", code),
+            ("redirect_uri", redirect_url),
         ])
         .header("Accept", "application/json")
         .send()
@@ -460,13 +540,12 @@ async fn github_auth_make_comment(
     let access_token_str: &str = access_token
         .as_str()
         .ok_or(Error::from("Github access_token was not a string!"))?;
-
     let mut reqw_resp: Option<reqwest::Response> = None;
     for _idx in 0..3 {
         let ret = client
             .get("https://api.github.com/user")
             .header("Accept", "application/vnd.github+json")
-            .header("Authorization", &format!("Bearer {}", access_token_str))
+            .header("Authorization", format!("Bearer {}", access_token_str))
             .header("X-Github-Api-Version", "2022-11-28")
             .send()
             .await
@@ -483,17 +562,12 @@ async fn github_auth_make_comment(
             sleep(Duration::from_secs(3)).await;
         }
     }
+
     let user_info: serde_json::Value = reqw_resp
         .ok_or(Error::from("Failed to get user info via oauth token!"))?
         .json()
         .await?;
 
-    let user_id: u64 = user_info
-        .get("id")
-        .ok_or(Error::from("Failed to parse user info id!"))?
-        .to_string()
-        .parse()?;
-
     let mut user_name: Option<&serde_json::Value> = user_info.get("name");
     let user_name_str: String;
 
@@ -520,6 +594,12 @@ async fn github_auth_make_comment(
             .to_owned();
     }
 
+    let user_id: u64 = user_info
+        .get("id")
+        .ok_or(Error::from("Failed to parse user info id!"))?
+        .to_string()
+        .parse()?;
+
     let user_login: String = user_info
         .get("login")
         .ok_or(Error::from("Failed to parse user info login!"))?
@@ -539,69 +619,85 @@ async fn github_auth_make_comment(
         .as_str()
         .ok_or(Error::from("Failed to parse user info profile avatar url!"))?;
 
-    sql::add_pseudo_comment_data(
-        sql_ctx.clone(),
-        &state,
-        user_id,
-        &user_name_str,
-        user_url,
-        user_avatar_url,
-        Some(&blog_id),
-        None,
-    )?;
-
-    let login_id = sql::add_login(
-        sql_ctx.clone(),
-        if config.x_real_ip_enabled {
-            req.header("x-real-ip")
+    let mut user_name_str: String = unicode::normalize_name(&user_name_str);
+    if user_name_str.is_empty() {
+        user_name_str = user_login.clone();
+    }
+    if config.check_confusable_names && imitates_admin(config, &user_name_str, &user_login) {
+        // Logins are unique on Github, so they are shown instead, marked if
+        // they look like an admin's too.
+        if imitates_admin(config, &user_login, &user_login) {
+            println!(
+                "User \"{}\" ({}) has a login that looks like an admin's.",
+                user_login, user_id
+            );
+            user_name_str = format!("{} (not an admin)", user_login);
         } else {
-            None
-        },
-        user_id,
-        &user_name_str,
-        &user_login,
-        user_url,
-        user_avatar_url,
-    )?;
+            user_name_str = user_login.clone();
+        }
+    }
 
-    let login_setup_script_with_id = LOGIN_SETUP_SCRIPT.replace("{LOGIN_ID}", &login_id);
+    Ok(GithubUser {
+        id: user_id,
+        name: user_name_str,
+        login: user_login,
+        url: user_url.to_owned(),
+        avatar_url: user_avatar_url.to_owned(),
+    })
+}
 
-    res.body(
-        WRITE_COMMENT_PAGE
-            .replace("{BLOG_ID}", &blog_id)
-            .replace("{COMMON_CSS}", COMMON_CSS)
-            .replace("{USER_AVATAR_URL}", user_avatar_url)
-            .replace("{USER_NAME}", &user_name_str)
-            .replace("{USER_PROFILE}", user_url)
-            .replace("{BASE_URL}", &config.base_url)
-            .replace("{BLOG_URL}", &blog_url)
-            .replace("{STATE_STRING}", &state)
-            .replace("{LOGIN_SETUP}", &login_setup_script_with_id),
-    );
-    Ok(())
+fn get_blog(config: &Config, sql_ctx: SQLCtx, blog_id: &str) -> Result<Option<sql::Blog>, Error> {
+    sql::get_blog(
+        sql_ctx,
+        blog_id,
+        config.auto_close_days,
+        config.auto_close_from,
+    )
 }
 
-#[handler]
-async fn submit_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
-    let request_json: serde_json::Value =
-        req.parse_json().await.map_err(Error::err_to_client_err)?;
+// Blogs that aren't registered yet are open if they match an "allowed_bid"
+// pattern.
+fn is_blog_closed(config: &Config, sql_ctx: SQLCtx, blog_id: &str) -> Result<bool, Error> {
+    if let Some(blog) = get_blog(config, sql_ctx, blog_id)? {
+        Ok(blog.closed)
+    } else {
+        Ok(!allowlist::is_unregistered_bid_allowed(
+            &config.allowed_bids,
+            None,
+            blog_id,
+            "",
+        ))
+    }
+}
 
-    let req_state = request_json
-        .get("state")
-        .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?
-        .as_str()
-        .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?;
-    let req_comment = request_json
-        .get("comment_text")
-        .ok_or(Error::from("JSON parse error: \"comment_text\"").into_client_err())?
-        .as_str()
-        .ok_or(Error::from("JSON parse error: \"comment_text\"").into_client_err())?;
-
-    let config: &Config = depot.obtain().unwrap();
+// Returns why replying to "parent_id" is not allowed, if it isn't.
+fn check_reply_parent(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    parent_id: &str,
+) -> Result<Option<&'static str>, Error> {
+    match sql::get_reply_parent_depth(sql_ctx, blog_id, parent_id)? {
+        None => Ok(Some("The comment being replied to does not exist.")),
+        Some(depth) if depth + 1 > config.max_reply_depth => {
+            Ok(Some("Replies cannot be nested any deeper."))
+        }
+        Some(_) => Ok(None),
+    }
+}
 
-    let pseudo_comment: sql::PseudoComment =
-        sql::add_comment(config.into(), req_state, req_comment)?;
+// Returns the blog_id of the comment.
+fn invalidate_comment_blog(
+    cache: &CommentCache,
+    sql_ctx: SQLCtx,
+    comment_id: &str,
+) -> Result<String, Error> {
+    let blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx, comment_id)?;
+    cache.invalidate(&blog_id);
+    Ok(blog_id)
+}
 
+fn run_on_comment_cmds(config: &Config, pseudo_comment: &sql::PseudoComment) {
     for cmd in &config.on_comment_cmds {
         let cmd_res = std::process::Command::new("/usr/bin/sh")
             .args(["-c", cmd])
@@ -612,80 +708,360 @@ async fn submit_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Erro
             eprintln!("On comment: Failed to execute: {}", cmd);
         }
     }
+}
+
+fn closed_error(blog_id: &str) -> Error {
+    eprintln!("Client tried to comment on closed blog! {}", blog_id);
+    Error::coded(ErrorCode::BlogClosed, CLOSED_MESSAGE)
+}
+
+// The cached comments are shared by every viewer, so the viewer's own
+// reactions are marked on a copy.
+fn mark_reacted(comments: &mut [sql::Comment], reacted: &HashSet<(String, String)>) {
+    if reacted.is_empty() {
+        return;
+    }
+
+    for comment in comments.iter_mut() {
+        for reaction in comment.reactions.iter_mut() {
+            reaction.reacted =
+                reacted.contains(&(comment.comment_id.clone(), reaction.emoji.clone()));
+        }
+    }
+}
+
+// Adds or removes the user's reaction, and returns the blog id of the
+// comment.
+fn set_reaction(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    login: &sql::LoginInfo,
+    comment_id: &str,
+    emoji: &str,
+    add: bool,
+) -> Result<String, Error> {
+    if !config.reactions.iter().any(|reaction| reaction == emoji) {
+        return Err(Error::coded(
+            ErrorCode::InvalidReaction,
+            format!("Reactions must be one of {}.", config.reactions.join(" ")),
+        ));
+    }
+
+    let blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx.clone(), comment_id)?;
+    if is_blog_closed(config, sql_ctx.clone(), &blog_id)? {
+        return Err(closed_error(&blog_id));
+    }
+
+    if add {
+        if !sql::add_reaction(sql_ctx, comment_id, login.user_github_id, emoji)? {
+            return Err(Error::coded(
+                ErrorCode::CommentNotFound,
+                "Comment was deleted!",
+            ));
+        }
+    } else {
+        sql::remove_reaction(sql_ctx, comment_id, login.user_github_id, emoji)?;
+    }
+
+    Ok(blog_id)
+}
+
+// Returns the normalized comment if it is within the limits.
+fn clean_comment(config: &Config, comment: &str) -> Result<String, Error> {
+    let comment: &str = &unicode::normalize_comment(comment);
+    let limits: &config::CommentLimits = &config.comment_limits;
+
+    if comment.trim().is_empty() {
+        return Err(Error::coded(
+            ErrorCode::CommentEmpty,
+            "Comments must not be empty.",
+        ));
+    }
+
+    let chars: usize = comment.chars().count();
+    if chars < limits.min_chars {
+        return Err(Error::coded(
+            ErrorCode::CommentTooShort,
+            format!("Comments must be at least {} characters.", limits.min_chars),
+        ));
+    } else if chars > limits.max_chars {
+        return Err(Error::coded(
+            ErrorCode::CommentTooLong,
+            format!("Comments can be at most {} characters.", limits.max_chars),
+        ));
+    }
+
+    if comment.len() < limits.min_bytes {
+        return Err(Error::coded(
+            ErrorCode::CommentTooShort,
+            format!("Comments must be at least {} bytes.", limits.min_bytes),
+        ));
+    } else if comment.len() > limits.max_bytes {
+        return Err(Error::coded(
+            ErrorCode::CommentTooLong,
+            format!("Comments can be at most {} bytes.", limits.max_bytes),
+        ));
+    }
+
+    if limits.max_lines != 0 && comment.lines().count() > limits.max_lines {
+        return Err(Error::coded(
+            ErrorCode::TooManyLines,
+            format!("Comments can have at most {} lines.", limits.max_lines),
+        ));
+    }
+
+    if limits.max_links != 0 && LINK_REGEX.find_iter(comment).count() > limits.max_links {
+        return Err(Error::coded(
+            ErrorCode::TooManyLinks,
+            format!("Comments can have at most {} links.", limits.max_links),
+        ));
+    }
+
+    Ok(comment.to_owned())
+}
+
+// Bodies without a Content-Length fail to parse once they are over
+// max_body_size instead.
+#[handler]
+async fn check_body_size(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+    ctrl: &mut FlowCtrl,
+) {
+    let config: &Config = depot.obtain().unwrap();
+    let max_body_size: usize = config.max_body_size;
+
+    let too_large: bool = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<usize>().ok())
+        .is_some_and(|len| len > max_body_size);
+    if too_large {
+        Error::coded(
+            ErrorCode::BodyTooLarge,
+            format!("Request bodies can be at most {} bytes.", max_body_size),
+        )
+        .write(req, depot, res)
+        .await;
+        ctrl.skip_rest();
+    }
+}
+
+#[handler]
+async fn root_handler(res: &mut Response) {
+    res.body(format!(
+        "<html><head><style>{}</style></head><body><h1>Welcome</h1></body></html>",
+        COMMON_CSS
+    ));
+}
+
+#[handler]
+async fn comment_text_get(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = req
+        .try_query("comment_id")
+        .map_err(Error::err_to_client_err)?;
+
+    let (comment_text, modified_date) = sql::get_comment_text(config.into(), &comment_id)?;
+
+    // modified_date only has whole seconds, so the text itself is hashed.
+    let etag: String = http_cache::make_etag(&(&comment_id, &comment_text));
+    if http_cache::check_not_modified(
+        req,
+        res,
+        &config.comments_cache_control,
+        &etag,
+        Some(modified_date),
+    )? {
+        return Ok(());
+    }
+
+    res.body(comment_text);
 
     Ok(())
 }
 
 #[handler]
-async fn login_to_edit_comment(
+async fn comment_info_get(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), Error> {
     let config: &Config = depot.obtain().unwrap();
+
     let comment_id: String = req
         .try_query("comment_id")
         .map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let not_found = || Error::coded(ErrorCode::CommentNotFound, "Comment does not exist!");
+
+    let (blog_id, mut comment) =
+        sql::get_comment(sql_ctx.clone(), &comment_id, &config.reactions)?.ok_or_else(not_found)?;
+    if comment.deleted && config.hide_deleted_comments {
+        return Err(not_found());
+    }
+    let moderation: sql::CommentModeration =
+        sql::get_comment_moderation(sql_ctx.clone(), &comment_id)?.ok_or_else(not_found)?;
+    let blog: Option<sql::Blog> = sql::get_blog(
+        sql_ctx.clone(),
+        &blog_id,
+        config.auto_close_days,
+        config.auto_close_from,
+    )?;
+
+    let viewer_id: Option<u64> =
+        get_viewer(req, config, sql_ctx.clone())?.map(|login| login.user_github_id);
+
+    let version: sql::CommentsVersion = sql::get_comments_version(sql_ctx.clone(), &blog_id)?;
+    let etag: String = http_cache::make_etag(&(
+        &comment_id,
+        &version,
+        blog.as_ref().map(|blog| &blog.url),
+        viewer_id,
+    ));
+    if http_cache::check_not_modified(
+        req,
+        res,
+        &http_cache::viewer_cache_control(&config.comments_cache_control, viewer_id.is_some()),
+        &etag,
+        Some(moderation.modified_date),
+    )? {
+        return Ok(());
+    }
+
+    let author: Option<&str> = blog.as_ref().and_then(|blog| blog.author.as_deref());
+    set_badges(config, std::slice::from_mut(&mut comment), author);
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    renderer.render_comment(&mut comment);
+    if let Some(viewer_id) = viewer_id {
+        mark_reacted(
+            std::slice::from_mut(&mut comment),
+            &sql::get_user_reactions(sql_ctx, &blog_id, viewer_id)?,
+        );
+    }
+
+    let status: CommentStatus = if !comment.deleted {
+        CommentStatus::Visible
+    } else if moderation.deleted_by.as_deref() == login_from_userurl(&moderation.userurl) {
+        CommentStatus::Deleted
+    } else {
+        CommentStatus::Removed
+    };
+    // The commenter's own reason for deleting is kept private.
+    let delete_reason: Option<String> = match status {
+        CommentStatus::Removed => moderation.delete_reason,
+        _ => None,
+    };
+
+    let info = CommentInfo {
+        permalink: make_permalink(config, blog.as_ref(), &blog_id, &comment_id),
+        blog_id,
+        status,
+        deleted_date: moderation.deleted_date,
+        deleted_date_ms: moderation.deleted_date_ms,
+        delete_reason,
+        comment,
+    };
+
+    res.body(serde_json::to_string(&info)?);
+
+    Ok(())
+}
+
+#[handler]
+async fn login_to_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
     let blog_url: String = req
         .try_query("blog_url")
         .map_err(Error::err_to_client_err)?;
+    let salvo_conf = depot.obtain::<Config>().unwrap();
+    let config: &Config = depot.obtain().unwrap();
+    let is_allowed_url: bool = allowlist::is_url_allowed(&salvo_conf.allowed_urls, &blog_url);
+    if !is_allowed_url {
+        eprintln!("Client blog_url is invalid! {}", blog_url);
+        return Err(Error::coded(
+            ErrorCode::UrlNotAllowed,
+            "blog_url is not allowed.",
+        ));
+    }
+
     let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
-    let uuid = sql::create_rng_uuid(sql_ctx.clone(), Some(&comment_id))?;
-    let blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx.clone(), &comment_id)
-        .map_err(|e| e.into_client_err())?;
 
-    // Check if logged in.
-    sql::cleanup_logins(sql_ctx.clone(), config.login_timeout_minutes)?;
+    let is_allowed_bid: bool = if let Some(blog) = get_blog(config, sql_ctx.clone(), &blog_id)? {
+        if blog.closed {
+            return Err(closed_error(&blog_id));
+        }
+        true
+    } else {
+        // Unregistered blogs are registered on their first comment.
+        allowlist::is_unregistered_bid_allowed(
+            &config.allowed_bids,
+            config.blog_url_template.as_deref(),
+            &blog_id,
+            &blog_url,
+        )
+    };
+    if !is_allowed_bid {
+        eprintln!("Client blog id is invalid! {}", blog_id);
+        return Err(Error::coded(
+            ErrorCode::BlogNotAllowed,
+            "blog_id is not allowed.",
+        ));
+    }
+
+    let parent_id: Option<String> = req.try_query("parent_id").ok();
+    if let Some(parent_id) = &parent_id {
+        if let Some(reason) = check_reply_parent(config, sql_ctx.clone(), &blog_id, parent_id)? {
+            eprintln!("Client reply parent is invalid! {}", parent_id);
+            return Err(Error::coded(ErrorCode::InvalidReply, reason));
+        }
+    }
 
+    // Check if logged in.
     let mut login: Option<sql::LoginInfo> = None;
     let login_id: Result<String, _> = req.try_query("login_id");
     if let Ok(login_id) = login_id {
-        if config.get_x_real_ip_enabled()
-            && let Some(real_ip) = req.header("x-real-ip")
-        {
-            login = sql::check_logged_in(sql_ctx.clone(), &login_id, real_ip)?;
-        } else {
-            login =
-                sql::check_logged_in(sql_ctx.clone(), &login_id, &req.remote_addr().to_string())?;
-        }
+        login = get_login(req, config, sql_ctx.clone(), &login_id)?;
     }
 
     if let Some(login) = login {
         // Logged in.
-        let can_edit: bool = sql::check_edit_comment_auth(
+        let uuid = sql::create_pending_action(
             sql_ctx.clone(),
-            &comment_id,
-            &login.user_github_id.to_string(),
+            sql::PendingAction::Create,
+            &blog_id,
+            parent_id.as_deref(),
         )?;
-        if !can_edit {
-            eprintln!(
-                "User tried to edit comment they didn't make! {}",
-                &comment_id
-            );
-            res.status_code(StatusCode::BAD_REQUEST);
-            res.body(format!(
-                r#"<html><head><style>{}</style></head><body>
-                <b>Bad Request</b><br>
-                <p>You are not the commentor of the comment you are trying to edit.</p>
-                </body></html>"#,
-                COMMON_CSS,
-            ));
-            return Ok(());
-        }
-
-        sql::add_pseudo_comment_data(
+        sql::bind_pending_action_user(
             sql_ctx.clone(),
             &uuid,
+            sql::PendingAction::Create,
             login.user_github_id,
             &login.username,
             &login.userurl,
             &login.useravatar,
-            None,
-            Some(&comment_id),
         )?;
 
         res.body(
-            EDIT_COMMENT_PAGE
+            WRITE_COMMENT_PAGE
+                .replace(
+                    "{MAX_COMMENT_CHARS}",
+                    &config.comment_limits.max_chars.to_string(),
+                )
+                .replace("{BLOG_ID}", &blog_id)
                 .replace("{COMMON_CSS}", COMMON_CSS)
                 .replace("{USER_AVATAR_URL}", &login.useravatar)
                 .replace("{USER_NAME}", &login.username)
@@ -693,24 +1069,28 @@ async fn login_to_edit_comment(
                 .replace("{BASE_URL}", &config.base_url)
                 .replace(
                     "{BLOG_URL}",
-                    &format!("{}#{}comment{}", &blog_url, &blog_id, &comment_id),
+                    &format!("{}#{}comment{}", &blog_url, &blog_id, &uuid),
                 )
-                .replace("{COMMENT_ID}", &comment_id)
+                .replace("{STATE_STRING}", &uuid)
                 .replace("{LOGIN_SETUP}", ""),
         );
-
         return Ok(());
     }
 
     // Setup for Github auth.
+    let uuid = sql::create_pending_action(
+        sql_ctx.clone(),
+        sql::PendingAction::Create,
+        &blog_id,
+        parent_id.as_deref(),
+    )?;
     let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_edit_comment", config.base_url),
+        &format!("{}/github_auth_make_comment", salvo_conf.base_url),
         &[
-            ("comment_id", &comment_id),
             ("blog_id", &blog_id),
             (
                 "blog_url",
-                &format!("{}#{}comment{}", blog_url, &blog_id, &comment_id),
+                &format!("{}#{}comment{}", blog_url, &blog_id, &uuid),
             ),
         ],
     )
@@ -718,7 +1098,7 @@ async fn login_to_edit_comment(
     let github_api_url = Url::parse_with_params(
         "https://github.com/login/oauth/authorize",
         &[
-            ("client_id", config.oauth_user.as_str()),
+            ("client_id", salvo_conf.oauth_user.as_str()),
             ("state", uuid.as_str()),
             ("redirect_uri", redirect_url.as_str()),
         ],
@@ -733,6 +1113,7 @@ async fn login_to_edit_comment(
         "#,
         github_api_url.as_str()
     );
+
     res.body(format!(
         r#"<html><head><style>{}</style></head><body>
         <b>Redirecting to Github for Authentication...</b>
@@ -747,15 +1128,12 @@ async fn login_to_edit_comment(
 }
 
 #[handler]
-async fn github_auth_edit_comment(
+async fn github_auth_make_comment(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), Error> {
     let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
-    let comment_id: String = req
-        .try_query("comment_id")
-        .map_err(Error::err_to_client_err)?;
     let blog_url: String = req
         .try_query("blog_url")
         .map_err(Error::err_to_client_err)?;
@@ -766,202 +1144,97 @@ async fn github_auth_edit_comment(
 
     let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
 
-    let is_state_valid = sql::check_rng_uuid(sql_ctx.clone(), &comment_id, Some(&state))?;
+    let is_state_valid = sql::check_pending_action(
+        sql_ctx.clone(),
+        &state,
+        sql::PendingAction::Create,
+        &blog_id,
+    )?;
     if !is_state_valid {
         eprintln!("State is invalid (timed out?)!\n");
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request (took too long to verify)</b>
-            </body></html>"#,
-            COMMON_CSS,
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Took too long to verify, please try again.",
         ));
-        return Ok(());
     }
+
+    if is_blog_closed(config, sql_ctx.clone(), &blog_id)? {
+        sql::remove_pending_action(sql_ctx, &state)?;
+        return Err(closed_error(&blog_id));
+    }
+
     let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_edit_comment", config.base_url),
+        &format!("{}/github_auth_make_comment", config.base_url),
         &[
             ("blog_id", &blog_id),
             (
                 "blog_url",
-                &format!("{}#{}comment{}", blog_url, &blog_id, &comment_id),
+                &format!("{}#{}comment{}", blog_url, &blog_id, &state),
             ),
         ],
     )
     .map_err(|_| Error::from("Failed to parse redirect url!"))?;
 
-    let client = reqwest::Client::builder();
-    let client = client.user_agent(&config.user_agent).build()?;
-    let g_res = client
-        .post("https://github.com/login/oauth/access_token")
-        .query(&[
-            ("client_id", config.oauth_user.as_str()),
-            ("client_secret", config.oauth_token.as_str()),
-            ("code", code.as_str()),
-            ("redirect_uri", redirect_url.as_str()),
-        ])
-        .header("Accept", "application/json")
-        .send()
-        .await?;
+    let GithubUser {
+        id: user_id,
+        name: user_name_str,
+        login: user_login,
+        url: user_url,
+        avatar_url: user_avatar_url,
+    } = fetch_github_user(config, &code, redirect_url.as_str()).await?;
 
-    let json: serde_json::Value = g_res.json().await?;
-    let access_token = json.get("access_token").ok_or(Error::from(
-        "Failed to parse access_token from response from Github!",
-    ))?;
-    let access_token_str: &str = access_token
-        .as_str()
-        .ok_or(Error::from("Github access token was not a string!"))?;
-    let mut reqw_resp: Option<reqwest::Response> = None;
-    for _idx in 0..3 {
-        let ret = client
-            .get("https://api.github.com/user")
-            .header("Accept", "application/vnd.github+json")
-            .header("Authorization", format!("Bearer {}", access_token_str))
-            .header("X-Github-Api-Version", "2022-11-28")
-            .send()
-            .await
-            .map_err(Error::from);
-        if ret.is_ok() {
-            let ret = ret?.error_for_status();
-            if ret.is_ok() {
-                reqw_resp = Some(ret?);
-                break;
-            } else {
-                sleep(Duration::from_secs(3)).await;
-            }
+    sql::bind_pending_action_user(
+        sql_ctx.clone(),
+        &state,
+        sql::PendingAction::Create,
+        user_id,
+        &user_name_str,
+        &user_url,
+        &user_avatar_url,
+    )?;
+
+    let login_id = sql::add_login(
+        sql_ctx.clone(),
+        if config.x_real_ip_enabled {
+            req.header("x-real-ip")
         } else {
-            sleep(Duration::from_secs(3)).await;
-        }
-    }
-    let user_info: serde_json::Value = reqw_resp
-        .ok_or(Error::from("Failed to get user info via oauth token!"))?
-        .json()
-        .await?;
-
-    let user_id: u64 = user_info
-        .get("id")
-        .ok_or(Error::from("Failed to parse user info id!"))?
-        .to_string()
-        .parse()?;
-    let user_avatar = user_info
-        .get("avatar_url")
-        .ok_or(Error::from("Failed to parse user info avatar url!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info avatar url!"))?;
-    let mut user_name: Option<&serde_json::Value> = user_info.get("name");
-    let user_name_str: String;
-
-    if let Some(user_name_inner) = user_name {
-        if user_name_inner.is_string() {
-            user_name_str = user_name_inner
-                .as_str()
-                .ok_or(Error::from("Failed to parse user info name!"))?
-                .to_owned();
-        } else {
-            user_name = user_info.get("login");
-            user_name_str = user_name
-                .ok_or(Error::from("User has no name or login!"))?
-                .as_str()
-                .ok_or(Error::from("Failed to parse user info login!"))?
-                .to_owned();
-        }
-    } else {
-        user_name = user_info.get("login");
-        user_name_str = user_name
-            .ok_or(Error::from("User has no name or login!"))?
-            .as_str()
-            .ok_or(Error::from("Failed to parse user info login!"))?
-            .to_owned();
-    }
-
-    let user_login: String = user_info
-        .get("login")
-        .ok_or(Error::from("Failed to parse user info login!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info login!"))?
-        .to_string();
-
-    let user_url = user_info
-        .get("html_url")
-        .ok_or(Error::from("Failed to parse user info url!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info url!"))?;
-
-    let user_avatar_url = user_info
-        .get("avatar_url")
-        .ok_or(Error::from("Failed to parse user info profile avatar url!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info profile avatar url!"))?;
-
-    let can_edit: bool =
-        sql::check_edit_comment_auth(sql_ctx.clone(), &comment_id, &user_id.to_string())?;
-    if !can_edit {
-        eprintln!(
-            "User tried to edit comment they didn't make! {}",
-            &comment_id
-        );
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request</b><br>
-            <p>You are not the commentor of the comment you are trying to edit.</p>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
-    }
-
-    sql::add_pseudo_comment_data(
-        sql_ctx.clone(),
-        &state,
-        user_id,
-        &user_name_str,
-        user_url,
-        user_avatar,
-        None,
-        Some(&comment_id),
-    )?;
-
-    let login_id = sql::add_login(
-        sql_ctx.clone(),
-        if config.x_real_ip_enabled {
-            req.header("x-real-ip")
-        } else {
-            None
-        },
-        user_id,
-        &user_name_str,
-        &user_login,
-        user_url,
-        user_avatar_url,
-    )?;
+            None
+        },
+        user_id,
+        &user_name_str,
+        &user_login,
+        &user_url,
+        &user_avatar_url,
+    )?;
 
     let login_setup_script_with_id = LOGIN_SETUP_SCRIPT.replace("{LOGIN_ID}", &login_id);
 
     res.body(
-        EDIT_COMMENT_PAGE
+        WRITE_COMMENT_PAGE
+            .replace(
+                "{MAX_COMMENT_CHARS}",
+                &config.comment_limits.max_chars.to_string(),
+            )
+            .replace("{BLOG_ID}", &blog_id)
             .replace("{COMMON_CSS}", COMMON_CSS)
-            .replace("{USER_AVATAR_URL}", user_avatar)
+            .replace("{USER_AVATAR_URL}", &user_avatar_url)
             .replace("{USER_NAME}", &user_name_str)
-            .replace("{USER_PROFILE}", user_url)
+            .replace("{USER_PROFILE}", &user_url)
             .replace("{BASE_URL}", &config.base_url)
             .replace("{BLOG_URL}", &blog_url)
-            .replace("{COMMENT_ID}", &comment_id)
+            .replace("{STATE_STRING}", &state)
             .replace("{LOGIN_SETUP}", &login_setup_script_with_id),
     );
-
     Ok(())
 }
 
 #[handler]
-async fn submit_edit_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
-    let config: &Config = depot.obtain().unwrap();
-
+async fn submit_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
     let request_json: serde_json::Value =
         req.parse_json().await.map_err(Error::err_to_client_err)?;
 
-    let req_comment_id = request_json
-        .get("comment_id")
+    let req_state = request_json
+        .get("state")
         .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?
         .as_str()
         .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?;
@@ -971,105 +1244,140 @@ async fn submit_edit_comment(req: &mut Request, depot: &mut Depot) -> Result<(),
         .as_str()
         .ok_or(Error::from("JSON parse error: \"comment_text\"").into_client_err())?;
 
-    sql::edit_comment(config.into(), req_comment_id, req_comment)?;
+    let config: &Config = depot.obtain().unwrap();
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    if let Some(blog_id) =
+        sql::get_pending_action_target(sql_ctx.clone(), req_state, sql::PendingAction::Create)?
+        && is_blog_closed(config, sql_ctx.clone(), &blog_id)?
+    {
+        sql::remove_pending_action(sql_ctx, req_state)?;
+        return Err(closed_error(&blog_id));
+    }
+
+    let req_comment: String = clean_comment(config, req_comment)?;
+
+    let pseudo_comment: sql::PseudoComment = sql::add_comment(
+        sql_ctx.clone(),
+        req_state,
+        &req_comment,
+        config.blog_url_template.as_deref(),
+    )?;
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&pseudo_comment.blog_post_id);
+
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(
+        sql_ctx,
+        &pseudo_comment.blog_post_id,
+        EventKind::Created,
+        &pseudo_comment.comment_id,
+    );
+
+    run_on_comment_cmds(config, &pseudo_comment);
 
     Ok(())
 }
 
 #[handler]
-async fn login_to_delete_comment(
+async fn login_to_edit_comment(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), Error> {
     let config: &Config = depot.obtain().unwrap();
-
     let comment_id: String = req
         .try_query("comment_id")
         .map_err(Error::err_to_client_err)?;
     let blog_url: String = req
         .try_query("blog_url")
         .map_err(Error::err_to_client_err)?;
-    let uuid = sql::create_rng_uuid(config.into(), Some(&comment_id))?;
-
     let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+    let blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx.clone(), &comment_id)
+        .map_err(|e| e.into_client_err())?;
+    if is_blog_closed(config, sql_ctx.clone(), &blog_id)? {
+        return Err(closed_error(&blog_id));
+    }
 
     // Check if logged in.
-    sql::cleanup_logins(sql_ctx.clone(), config.login_timeout_minutes)?;
-
     let mut login: Option<sql::LoginInfo> = None;
     let login_id: Result<String, _> = req.try_query("login_id");
     if let Ok(login_id) = login_id {
-        if config.get_x_real_ip_enabled()
-            && let Some(real_ip) = req.header("x-real-ip")
-        {
-            login = sql::check_logged_in(sql_ctx.clone(), &login_id, real_ip)?;
-        } else {
-            login =
-                sql::check_logged_in(sql_ctx.clone(), &login_id, &req.remote_addr().to_string())?;
-        }
+        login = get_login(req, config, sql_ctx.clone(), &login_id)?;
     }
 
     if let Some(login) = login {
         // Logged in.
-        let is_admin: bool =
-            config.admins.iter().fold(
-                false,
-                |acc, elem| if acc { acc } else { elem == &login.userlogin },
-            );
-        let can_del: bool = sql::check_edit_comment_auth(
+        let can_edit: bool = sql::check_edit_comment_auth(
             sql_ctx.clone(),
             &comment_id,
             &login.user_github_id.to_string(),
         )?;
-        if !can_del && !is_admin {
+        if !can_edit {
             eprintln!(
-                "User tried to delete comment they didn't make! {}",
+                "User tried to edit comment they didn't make! {}",
                 &comment_id
             );
-            res.status_code(StatusCode::BAD_REQUEST);
-            res.body(format!(
-                r#"<html><head><style>{}</style></head><body>
-                <b>Bad Request</b><br>
-                <p>You are not the commentor of the comment you are trying to edit.</p>
-                </body></html>"#,
-                COMMON_CSS,
+            return Err(Error::coded(
+                ErrorCode::NotOwner,
+                "You are not the commenter of this comment.",
             ));
-            return Ok(());
         }
 
-        if is_admin {
-            sql::try_delete_comment_id_only(sql_ctx.clone(), &comment_id)?;
-        } else {
-            sql::try_delete_comment(sql_ctx.clone(), &comment_id, login.user_github_id)?;
-        }
+        let uuid = sql::create_pending_action(
+            sql_ctx.clone(),
+            sql::PendingAction::Edit,
+            &comment_id,
+            None,
+        )?;
+        sql::bind_pending_action_user(
+            sql_ctx.clone(),
+            &uuid,
+            sql::PendingAction::Edit,
+            login.user_github_id,
+            &login.username,
+            &login.userurl,
+            &login.useravatar,
+        )?;
 
-        let script = format!(
-            r#"
-                "use strict";
-                setTimeout(() => {{
-                    window.location = "{}";
-                }}, 5000);
-            "#,
-            blog_url
+        res.body(
+            EDIT_COMMENT_PAGE
+                .replace(
+                    "{MAX_COMMENT_CHARS}",
+                    &config.comment_limits.max_chars.to_string(),
+                )
+                .replace("{COMMON_CSS}", COMMON_CSS)
+                .replace("{USER_AVATAR_URL}", &login.useravatar)
+                .replace("{USER_NAME}", &login.username)
+                .replace("{USER_PROFILE}", &login.userurl)
+                .replace("{BASE_URL}", &config.base_url)
+                .replace(
+                    "{BLOG_URL}",
+                    &format!("{}#{}comment{}", &blog_url, &blog_id, &comment_id),
+                )
+                .replace("{COMMENT_ID}", &comment_id)
+                .replace("{STATE_STRING}", &uuid)
+                .replace("{LOGIN_SETUP}", ""),
         );
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Attempted Comment Delete, reloading blog url...</b>
-            <script>
-            {}
-            </script>
-            </body></html>"#,
-            COMMON_CSS, script
-        ));
 
         return Ok(());
     }
 
     // Setup for Github auth.
+    let uuid =
+        sql::create_pending_action(sql_ctx.clone(), sql::PendingAction::Edit, &comment_id, None)?;
     let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_del_comment", config.base_url),
-        &[("comment_id", comment_id), ("blog_url", blog_url)],
+        &format!("{}/github_auth_edit_comment", config.base_url),
+        &[
+            ("comment_id", &comment_id),
+            ("blog_id", &blog_id),
+            (
+                "blog_url",
+                &format!("{}#{}comment{}", blog_url, &blog_id, &comment_id),
+            ),
+        ],
     )
     .map_err(|_| Error::from("Failed to parse redirect url!"))?;
     let github_api_url = Url::parse_with_params(
@@ -1090,238 +1398,982 @@ async fn login_to_delete_comment(
         "#,
         github_api_url.as_str()
     );
-    res.body(format!(
-        r#"<html><head><style>{}</style></head><body>
-        <b>Redirecting to Github for Authentication...</b>
-        <script>
-        {}
-        </script>
-        </body></html>"#,
-        COMMON_CSS, script
-    ));
+    res.body(format!(
+        r#"<html><head><style>{}</style></head><body>
+        <b>Redirecting to Github for Authentication...</b>
+        <script>
+        {}
+        </script>
+        </body></html>"#,
+        COMMON_CSS, script
+    ));
+
+    Ok(())
+}
+
+#[handler]
+async fn github_auth_edit_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
+    let comment_id: String = req
+        .try_query("comment_id")
+        .map_err(Error::err_to_client_err)?;
+    let blog_url: String = req
+        .try_query("blog_url")
+        .map_err(Error::err_to_client_err)?;
+    let state: String = req.try_query("state").map_err(Error::err_to_client_err)?;
+    let code: String = req.try_query("code").map_err(Error::err_to_client_err)?;
+
+    let config: &Config = depot.obtain().unwrap();
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let is_state_valid = sql::check_pending_action(
+        sql_ctx.clone(),
+        &state,
+        sql::PendingAction::Edit,
+        &comment_id,
+    )?;
+    if !is_state_valid {
+        eprintln!("State is invalid (timed out?)!\n");
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Took too long to verify, please try again.",
+        ));
+    }
+
+    let comment_blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx.clone(), &comment_id)
+        .map_err(|e| e.into_client_err())?;
+    if is_blog_closed(config, sql_ctx.clone(), &comment_blog_id)? {
+        sql::remove_pending_action(sql_ctx, &state)?;
+        return Err(closed_error(&comment_blog_id));
+    }
+    let redirect_url = Url::parse_with_params(
+        &format!("{}/github_auth_edit_comment", config.base_url),
+        &[
+            ("blog_id", &blog_id),
+            (
+                "blog_url",
+                &format!("{}#{}comment{}", blog_url, &blog_id, &comment_id),
+            ),
+        ],
+    )
+    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
+
+    let GithubUser {
+        id: user_id,
+        name: user_name_str,
+        login: user_login,
+        url: user_url,
+        avatar_url: user_avatar_url,
+    } = fetch_github_user(config, &code, redirect_url.as_str()).await?;
+
+    let can_edit: bool =
+        sql::check_edit_comment_auth(sql_ctx.clone(), &comment_id, &user_id.to_string())?;
+    if !can_edit {
+        eprintln!(
+            "User tried to edit comment they didn't make! {}",
+            &comment_id
+        );
+        return Err(Error::coded(
+            ErrorCode::NotOwner,
+            "You are not the commenter of this comment.",
+        ));
+    }
+
+    sql::bind_pending_action_user(
+        sql_ctx.clone(),
+        &state,
+        sql::PendingAction::Edit,
+        user_id,
+        &user_name_str,
+        &user_url,
+        &user_avatar_url,
+    )?;
+
+    let login_id = sql::add_login(
+        sql_ctx.clone(),
+        if config.x_real_ip_enabled {
+            req.header("x-real-ip")
+        } else {
+            None
+        },
+        user_id,
+        &user_name_str,
+        &user_login,
+        &user_url,
+        &user_avatar_url,
+    )?;
+
+    let login_setup_script_with_id = LOGIN_SETUP_SCRIPT.replace("{LOGIN_ID}", &login_id);
+
+    res.body(
+        EDIT_COMMENT_PAGE
+            .replace(
+                "{MAX_COMMENT_CHARS}",
+                &config.comment_limits.max_chars.to_string(),
+            )
+            .replace("{COMMON_CSS}", COMMON_CSS)
+            .replace("{USER_AVATAR_URL}", &user_avatar_url)
+            .replace("{USER_NAME}", &user_name_str)
+            .replace("{USER_PROFILE}", &user_url)
+            .replace("{BASE_URL}", &config.base_url)
+            .replace("{BLOG_URL}", &blog_url)
+            .replace("{COMMENT_ID}", &comment_id)
+            .replace("{STATE_STRING}", &state)
+            .replace("{LOGIN_SETUP}", &login_setup_script_with_id),
+    );
+
+    Ok(())
+}
+
+#[handler]
+async fn submit_edit_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let req_comment_id = request_json
+        .get("comment_id")
+        .ok_or(Error::from("JSON parse error: \"comment_id\"").into_client_err())?
+        .as_str()
+        .ok_or(Error::from("JSON parse error: \"comment_id\"").into_client_err())?;
+    let req_state = request_json
+        .get("state")
+        .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?
+        .as_str()
+        .ok_or(Error::from("JSON parse error: \"state\"").into_client_err())?;
+    let req_comment = request_json
+        .get("comment_text")
+        .ok_or(Error::from("JSON parse error: \"comment_text\"").into_client_err())?
+        .as_str()
+        .ok_or(Error::from("JSON parse error: \"comment_text\"").into_client_err())?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let blog_id: String = sql::get_blog_id_by_comment_id(sql_ctx.clone(), req_comment_id)
+        .map_err(|e| e.into_client_err())?;
+    if is_blog_closed(config, sql_ctx.clone(), &blog_id)? {
+        sql::remove_pending_action(sql_ctx, req_state)?;
+        return Err(closed_error(&blog_id));
+    }
+
+    let req_comment: String = clean_comment(config, req_comment)?;
+
+    sql::edit_comment(sql_ctx.clone(), req_state, req_comment_id, &req_comment)?;
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&blog_id);
+
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(sql_ctx, &blog_id, EventKind::Edited, req_comment_id);
+
+    Ok(())
+}
+
+#[handler]
+async fn login_to_delete_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = req
+        .try_query("comment_id")
+        .map_err(Error::err_to_client_err)?;
+    let blog_url: String = req
+        .try_query("blog_url")
+        .map_err(Error::err_to_client_err)?;
+    let reason: Option<String> = req.try_query("reason").ok();
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    // Check if logged in.
+    let mut login: Option<sql::LoginInfo> = None;
+    let login_id: Result<String, _> = req.try_query("login_id");
+    if let Ok(login_id) = login_id {
+        login = get_login(req, config, sql_ctx.clone(), &login_id)?;
+    }
+
+    if let Some(login) = login {
+        // Logged in.
+        let is_admin: bool = is_admin(config, &login.userlogin);
+        let can_del: bool = sql::check_edit_comment_auth(
+            sql_ctx.clone(),
+            &comment_id,
+            &login.user_github_id.to_string(),
+        )?;
+        if !can_del && !is_admin {
+            eprintln!(
+                "User tried to delete comment they didn't make! {}",
+                &comment_id
+            );
+            return Err(Error::coded(
+                ErrorCode::NotOwner,
+                "You are not the commenter of this comment.",
+            ));
+        }
+
+        let deleted: bool = if is_admin {
+            sql::try_delete_comment_id_only(
+                sql_ctx.clone(),
+                &comment_id,
+                &login.userlogin,
+                reason.as_deref(),
+                None,
+            )?
+        } else {
+            sql::try_delete_comment(
+                sql_ctx.clone(),
+                &comment_id,
+                login.user_github_id,
+                &login.userlogin,
+                reason.as_deref(),
+                None,
+            )?
+        };
+        let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+        let blog_id: String = invalidate_comment_blog(cache, sql_ctx.clone(), &comment_id)?;
+        if deleted {
+            let events: &Arc<EventHub> = depot.obtain().unwrap();
+            events.publish(sql_ctx.clone(), &blog_id, EventKind::Deleted, &comment_id);
+        }
+
+        let script = format!(
+            r#"
+                "use strict";
+                setTimeout(() => {{
+                    window.location = "{}";
+                }}, 5000);
+            "#,
+            blog_url
+        );
+        res.body(format!(
+            r#"<html><head><style>{}</style></head><body>
+            <b>Attempted Comment Delete, reloading blog url...</b>
+            <script>
+            {}
+            </script>
+            </body></html>"#,
+            COMMON_CSS, script
+        ));
+
+        return Ok(());
+    }
+
+    // Setup for Github auth.
+    let uuid = sql::create_pending_action(
+        sql_ctx.clone(),
+        sql::PendingAction::Delete,
+        &comment_id,
+        None,
+    )?;
+    let mut redirect_params: Vec<(&str, String)> =
+        vec![("comment_id", comment_id), ("blog_url", blog_url)];
+    if let Some(reason) = reason {
+        redirect_params.push(("reason", reason));
+    }
+    let redirect_url = Url::parse_with_params(
+        &format!("{}/github_auth_del_comment", config.base_url),
+        &redirect_params,
+    )
+    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
+    let github_api_url = Url::parse_with_params(
+        "https://github.com/login/oauth/authorize",
+        &[
+            ("client_id", config.oauth_user.as_str()),
+            ("state", uuid.as_str()),
+            ("redirect_uri", redirect_url.as_str()),
+        ],
+    )
+    .map_err(|_| Error::from("Failed to parse github api url!"))?;
+    let script = format!(
+        r#"
+            "use strict";
+            setTimeout(() => {{
+                window.location = "{}";
+            }}, 3000);
+        "#,
+        github_api_url.as_str()
+    );
+    res.body(format!(
+        r#"<html><head><style>{}</style></head><body>
+        <b>Redirecting to Github for Authentication...</b>
+        <script>
+        {}
+        </script>
+        </body></html>"#,
+        COMMON_CSS, script
+    ));
+
+    Ok(())
+}
+
+#[handler]
+async fn github_auth_del_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let comment_id: String = req
+        .try_query("comment_id")
+        .map_err(Error::err_to_client_err)?;
+    let blog_url: String = req
+        .try_query("blog_url")
+        .map_err(Error::err_to_client_err)?;
+    let reason: Option<String> = req.try_query("reason").ok();
+    let state: String = req.try_query("state").map_err(Error::err_to_client_err)?;
+    let code: String = req.try_query("code").map_err(Error::err_to_client_err)?;
+
+    let config: &Config = depot.obtain().unwrap();
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let is_state_valid = sql::check_pending_action(
+        sql_ctx.clone(),
+        &state,
+        sql::PendingAction::Delete,
+        &comment_id,
+    )?;
+    if !is_state_valid {
+        eprintln!("State is invalid (timed out?)!\n");
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Took too long to verify, please try again.",
+        ));
+    }
+
+    let mut redirect_params: Vec<(&str, &str)> =
+        vec![("comment_id", &comment_id), ("blog_url", &blog_url)];
+    if let Some(reason) = &reason {
+        redirect_params.push(("reason", reason));
+    }
+    let redirect_url = Url::parse_with_params(
+        &format!("{}/github_auth_del_comment", config.base_url),
+        &redirect_params,
+    )
+    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
+
+    let GithubUser {
+        id: user_id,
+        name: user_name_str,
+        login: user_login,
+        url: user_url,
+        avatar_url: user_avatar_url,
+    } = fetch_github_user(config, &code, redirect_url.as_str()).await?;
+
+    let is_admin: bool = is_admin(config, &user_login);
+
+    let can_del: bool =
+        sql::check_edit_comment_auth(sql_ctx.clone(), &comment_id, &user_id.to_string())?;
+    if !can_del && !is_admin {
+        eprintln!(
+            "User tried to delete comment they didn't make! {}",
+            &comment_id
+        );
+        return Err(Error::coded(
+            ErrorCode::NotOwner,
+            "You are not the commenter of this comment.",
+        ));
+    }
+
+    let deleted: bool = if is_admin {
+        sql::try_delete_comment_id_only(
+            sql_ctx.clone(),
+            &comment_id,
+            &user_login,
+            reason.as_deref(),
+            Some(&state),
+        )?
+    } else {
+        sql::try_delete_comment(
+            sql_ctx.clone(),
+            &comment_id,
+            user_id,
+            &user_login,
+            reason.as_deref(),
+            Some(&state),
+        )?
+    };
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    let blog_id: String = invalidate_comment_blog(cache, sql_ctx.clone(), &comment_id)?;
+    if deleted {
+        let events: &Arc<EventHub> = depot.obtain().unwrap();
+        events.publish(sql_ctx.clone(), &blog_id, EventKind::Deleted, &comment_id);
+    }
+
+    let login_id = sql::add_login(
+        sql_ctx.clone(),
+        if config.x_real_ip_enabled {
+            req.header("x-real-ip")
+        } else {
+            None
+        },
+        user_id,
+        &user_name_str,
+        &user_login,
+        &user_url,
+        &user_avatar_url,
+    )?;
+
+    let script = format!(
+        r#"
+            "use strict";
+            setTimeout(() => {{
+                window.localStorage.setItem("seodisp_comments_login_id", "{}");
+                window.location = "{}";
+            }}, 5000);
+        "#,
+        login_id, blog_url
+    );
+    res.body(format!(
+        r#"<html><head><style>{}</style></head><body>
+        <b>Attempted Comment Delete, reloading blog url...</b>
+        <script>
+        {}
+        </script>
+        </body></html>"#,
+        COMMON_CSS, script
+    ));
+
+    Ok(())
+}
+
+#[handler]
+async fn get_comments_by_blog_id(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
+    let format: Option<String> = req.try_query("format").ok();
+    let limit: Option<usize> = req
+        .try_query::<String>("limit")
+        .ok()
+        .map(|limit| limit.parse::<usize>())
+        .transpose()
+        .map_err(Error::err_to_client_err)?;
+    let cursor: Option<String> = req.try_query("cursor").ok();
+    let order: Option<pagination::Order> = req
+        .try_query::<String>("order")
+        .ok()
+        .map(|order| pagination::Order::parse(&order))
+        .transpose()?;
+
+    if let Some(limit) = limit
+        && (limit == 0 || limit > pagination::MAX_LIMIT)
+    {
+        return Err(Error::from(format!(
+            "limit must be between 1 and {}!",
+            pagination::MAX_LIMIT
+        ))
+        .into_client_err());
+    }
+    let is_tree: bool = match format.as_deref() {
+        None | Some("flat") => false,
+        Some("tree") => true,
+        Some(_) => {
+            return Err(Error::from("format must be \"flat\" or \"tree\"!").into_client_err());
+        }
+    };
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let closed: bool = is_blog_closed(config, sql_ctx.clone(), &blog_id)?;
+
+    res.add_header("X-Comments-Closed", closed.to_string(), true)
+        .map_err(|_| Error::from("Failed to set X-Comments-Closed header!"))?;
+    // Lets cross-origin scripts read the header.
+    res.add_header("Access-Control-Expose-Headers", "X-Comments-Closed", true)
+        .map_err(|_| Error::from("Failed to set Access-Control-Expose-Headers header!"))?;
+
+    let viewer_id: Option<u64> =
+        get_viewer(req, config, sql_ctx.clone())?.map(|login| login.user_github_id);
+
+    let version: sql::CommentsVersion = sql::get_comments_version(sql_ctx.clone(), &blog_id)?;
+    let etag: String = http_cache::make_etag(&(
+        &blog_id, &version, closed, is_tree, limit, &cursor, order, viewer_id,
+    ));
+    if http_cache::check_not_modified(
+        req,
+        res,
+        &http_cache::viewer_cache_control(&config.comments_cache_control, viewer_id.is_some()),
+        &etag,
+        version.last_modified,
+    )? {
+        return Ok(());
+    }
+
+    // The bare array is returned unless one of the pagination parameters is
+    // given.
+    let paginated: bool = limit.is_some() || cursor.is_some() || order.is_some();
+
+    let renderer: &Arc<Renderer> = depot.obtain().unwrap();
+    let (mut comments, next_cursor, total) = if paginated {
+        fetch_comment_page(
+            config,
+            sql_ctx.clone(),
+            renderer,
+            &blog_id,
+            order.unwrap_or(pagination::Order::Oldest),
+            limit.unwrap_or(pagination::MAX_LIMIT),
+            cursor.as_deref(),
+        )?
+    } else {
+        let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+        let comments: Vec<sql::Comment> =
+            Arc::unwrap_or_clone(cache.get_or_fetch(&blog_id, || {
+                fetch_comments(config, sql_ctx.clone(), renderer, &blog_id)
+            })?);
+        (comments, None, 0)
+    };
+    if let Some(viewer_id) = viewer_id {
+        mark_reacted(
+            &mut comments,
+            &sql::get_user_reactions(sql_ctx, &blog_id, viewer_id)?,
+        );
+    }
+
+    let json: String = if paginated {
+        if is_tree {
+            serde_json::to_string(&pagination::Page {
+                comments: thread::to_tree(comments),
+                next_cursor,
+                total,
+                closed,
+            })?
+        } else {
+            serde_json::to_string(&pagination::Page {
+                comments,
+                next_cursor,
+                total,
+                closed,
+            })?
+        }
+    } else if is_tree {
+        serde_json::to_string(&thread::to_tree(comments))?
+    } else {
+        serde_json::to_string(&comments)?
+    };
+
+    res.body(json);
+
+    Ok(())
+}
+
+#[handler]
+async fn comment_events(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
+
+    stream_comment_events(req, res, depot, blog_id);
+
+    Ok(())
+}
+
+// Browsers send the id of the last event they got when reconnecting.
+fn stream_comment_events(req: &Request, res: &mut Response, depot: &Depot, blog_id: String) {
+    let config: &Config = depot.obtain().unwrap();
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+
+    let last_event_id: Option<u64> = req
+        .header::<String>("last-event-id")
+        .and_then(|id| id.parse().ok());
+
+    events::stream(
+        events,
+        blog_id,
+        last_event_id,
+        Duration::from_secs(config.events_heartbeat_seconds),
+        res,
+    );
+}
+
+#[handler]
+async fn search_comments(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let query: search::SearchQuery = req.parse_queries().map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    if !config.public_search {
+        let login_id: String = req
+            .try_query("login_id")
+            .map_err(Error::err_to_client_err)?;
+        get_admin_login(req, config, sql_ctx.clone(), &login_id)?;
+    }
+
+    let page: search::SearchPage = search::run(sql_ctx, &query)?;
+
+    res.body(serde_json::to_string(&page)?);
+
+    Ok(())
+}
+
+#[handler]
+async fn get_comment_counts(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let blog_ids_str: String = req
+        .try_query("blog_ids")
+        .map_err(Error::err_to_client_err)?;
+    let mut blog_ids: Vec<String> = blog_ids_str
+        .split(',')
+        .filter(|blog_id| !blog_id.is_empty())
+        .map(str::to_owned)
+        .collect();
+    blog_ids.sort();
+    blog_ids.dedup();
+
+    if blog_ids.len() > MAX_COMMENT_COUNTS_BLOG_IDS {
+        return Err(Error::from(format!(
+            "At most {} blog_ids can be requested at once!",
+            MAX_COMMENT_COUNTS_BLOG_IDS
+        ))
+        .into_client_err());
+    }
+
+    let counts = sql::get_comment_counts(config.into(), &blog_ids)?;
+
+    let json: String = serde_json::to_string(&counts)?;
+
+    res.add_header(
+        "Cache-Control",
+        format!("public, max-age={}", config.comment_counts_max_age_seconds),
+        true,
+    )
+    .map_err(|_| Error::from("Failed to set Cache-Control header!"))?;
+
+    res.body(json);
+
+    Ok(())
+}
+
+#[handler]
+async fn get_comment_revisions(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let comment_id: String = req
+        .try_query("comment_id")
+        .map_err(Error::err_to_client_err)?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let mut is_admin_login: bool = false;
+    let login_id: Result<String, _> = req.try_query("login_id");
+    if let Ok(login_id) = login_id
+        && let Some(login) = get_login(req, config, sql_ctx.clone(), &login_id)?
+    {
+        is_admin_login = is_admin(config, &login.userlogin);
+    }
+
+    if !config.public_revision_history && !is_admin_login {
+        return Err(Error::coded(
+            ErrorCode::NotAdmin,
+            "Revision history is only available to admins!",
+        ));
+    }
+
+    let revisions = sql::get_comment_revisions(sql_ctx, &comment_id, is_admin_login)?.ok_or(
+        Error::coded(ErrorCode::CommentNotFound, "Comment does not exist!"),
+    )?;
+
+    let json: String = serde_json::to_string(&revisions)?;
+
+    res.body(json);
+
+    Ok(())
+}
+
+#[handler]
+async fn admin_get_deleted_comments(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let login_id: String = req
+        .try_query("login_id")
+        .map_err(Error::err_to_client_err)?;
+    let blog_id: Option<String> = req.try_query("blog_id").ok();
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    get_admin_login(req, config, sql_ctx.clone(), &login_id)?;
+
+    let comments = sql::get_deleted_comments(sql_ctx, blog_id.as_deref())?;
+
+    let json: String = serde_json::to_string(&comments)?;
+
+    res.body(json);
+
+    Ok(())
+}
+
+#[handler]
+async fn admin_restore_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let req_login_id = request_json
+        .get("login_id")
+        .ok_or(Error::from("JSON parse error: \"login_id\"").into_client_err())?
+        .as_str()
+        .ok_or(Error::from("JSON parse error: \"login_id\"").into_client_err())?;
+    let req_comment_id = request_json
+        .get("comment_id")
+        .ok_or(Error::from("JSON parse error: \"comment_id\"").into_client_err())?
+        .as_str()
+        .ok_or(Error::from("JSON parse error: \"comment_id\"").into_client_err())?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login = get_admin_login(req, config, sql_ctx.clone(), req_login_id)?;
+
+    if !sql::restore_comment(sql_ctx.clone(), req_comment_id)? {
+        return Err(Error::coded(
+            ErrorCode::CommentNotDeleted,
+            "Comment is not deleted!",
+        ));
+    }
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    let blog_id: String = invalidate_comment_blog(cache, sql_ctx.clone(), req_comment_id)?;
+    let events: &Arc<EventHub> = depot.obtain().unwrap();
+    events.publish(sql_ctx, &blog_id, EventKind::Created, req_comment_id);
+
+    println!(
+        "Admin \"{}\" restored comment {}",
+        &login.userlogin, req_comment_id
+    );
+
+    Ok(())
+}
+
+#[handler]
+async fn admin_pin_comment(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let req_login_id: &str = json_get_str(&request_json, "login_id")?;
+    let req_comment_id: &str = json_get_str(&request_json, "comment_id")?;
+    let req_pinned: bool = request_json
+        .get("pinned")
+        .and_then(|val| val.as_bool())
+        .unwrap_or(true);
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login = get_admin_login(req, config, sql_ctx.clone(), req_login_id)?;
+
+    if !sql::set_comment_pinned(sql_ctx.clone(), req_comment_id, req_pinned)? {
+        return Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist, is deleted, or is a reply!",
+        ));
+    }
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    invalidate_comment_blog(cache, sql_ctx, req_comment_id)?;
+
+    println!(
+        "Admin \"{}\" {} comment {}",
+        &login.userlogin,
+        if req_pinned { "pinned" } else { "unpinned" },
+        req_comment_id
+    );
+
+    Ok(())
+}
+
+async fn change_reaction(req: &mut Request, depot: &mut Depot, add: bool) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
+
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
+
+    let req_login_id: &str = json_get_str(&request_json, "login_id")?;
+    let req_comment_id: &str = json_get_str(&request_json, "comment_id")?;
+    let req_emoji: &str = json_get_str(&request_json, "emoji")?;
+
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+
+    let login: sql::LoginInfo = get_login(req, config, sql_ctx.clone(), req_login_id)?
+        .ok_or(Error::coded(ErrorCode::NotLoggedIn, "Not logged in!"))?;
+
+    let blog_id: String = set_reaction(config, sql_ctx, &login, req_comment_id, req_emoji, add)?;
+
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(&blog_id);
 
     Ok(())
 }
 
 #[handler]
-async fn github_auth_del_comment(
+async fn add_reaction(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    change_reaction(req, depot, true).await
+}
+
+#[handler]
+async fn remove_reaction(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    change_reaction(req, depot, false).await
+}
+
+#[handler]
+async fn admin_get_cache_stats(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), Error> {
-    let comment_id: String = req
-        .try_query("comment_id")
-        .map_err(Error::err_to_client_err)?;
-    let blog_url: String = req
-        .try_query("blog_url")
+    let config: &Config = depot.obtain().unwrap();
+
+    let login_id: String = req
+        .try_query("login_id")
         .map_err(Error::err_to_client_err)?;
-    let state: String = req.try_query("state").map_err(Error::err_to_client_err)?;
-    let code: String = req.try_query("code").map_err(Error::err_to_client_err)?;
 
-    let config: &Config = depot.obtain().unwrap();
-    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
+    get_admin_login(req, config, config.into(), &login_id)?;
 
-    let is_state_valid = sql::check_rng_uuid(sql_ctx.clone(), &comment_id, Some(&state))?;
-    if !is_state_valid {
-        eprintln!("State is invalid (timed out?)!\n");
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request (took too long to verify)</b>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
-    }
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    res.body(serde_json::to_string(&cache.stats())?);
 
-    let redirect_url = Url::parse_with_params(
-        &format!("{}/github_auth_del_comment", config.base_url),
-        &[("comment_id", &comment_id), ("blog_url", &blog_url)],
-    )
-    .map_err(|_| Error::from("Failed to parse redirect url!"))?;
+    Ok(())
+}
 
-    let client = reqwest::Client::builder();
-    let client = client.user_agent(&config.user_agent).build()?;
-    let g_res = client
-        .post("https://github.com/login/oauth/access_token")
-        .query(&[
-            ("client_id", config.oauth_user.as_str()),
-            ("client_secret", config.oauth_token.as_str()),
-            ("code", code.as_str()),
-            ("redirect_uri", redirect_url.as_str()),
-        ])
-        .header("Accept", "application/json")
-        .send()
-        .await?;
+#[handler]
+async fn admin_get_blogs(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
 
-    let json: serde_json::Value = g_res.json().await?;
-    let access_token = json.get("access_token").ok_or(Error::from(
-        "Failed to parse access_token from response from Github!",
-    ))?;
-    let access_token_str: &str = access_token
-        .as_str()
-        .ok_or(Error::from("Github access_token was not a string!"))?;
-    let mut reqw_resp: Option<reqwest::Response> = None;
-    for _idx in 0..3 {
-        let ret = client
-            .get("https://api.github.com/user")
-            .header("Accept", "application/vnd.github+json")
-            .header("Authorization", format!("Bearer {}", access_token_str))
-            .header("X-Github-Api-Version", "2022-11-28")
-            .send()
-            .await
-            .map_err(Error::from);
-        if ret.is_ok() {
-            let ret = ret?.error_for_status();
-            if ret.is_ok() {
-                reqw_resp = Some(ret?);
-                break;
-            } else {
-                sleep(Duration::from_secs(3)).await;
-            }
-        } else {
-            sleep(Duration::from_secs(3)).await;
-        }
-    }
+    let login_id: String = req
+        .try_query("login_id")
+        .map_err(Error::err_to_client_err)?;
 
-    let user_info: serde_json::Value = reqw_resp
-        .ok_or(Error::from("Failed to get user info via oauth token!"))?
-        .json()
-        .await?;
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
 
-    let mut user_name: Option<&serde_json::Value> = user_info.get("name");
-    let user_name_str: String;
+    get_admin_login(req, config, sql_ctx.clone(), &login_id)?;
 
-    if let Some(user_name_inner) = user_name {
-        if user_name_inner.is_string() {
-            user_name_str = user_name_inner
-                .as_str()
-                .ok_or(Error::from("Failed to parse user info name!"))?
-                .to_owned();
-        } else {
-            user_name = user_info.get("login");
-            user_name_str = user_name
-                .ok_or(Error::from("User has no name or login!"))?
-                .as_str()
-                .ok_or(Error::from("Failed to parse user info login!"))?
-                .to_owned();
-        }
-    } else {
-        user_name = user_info.get("login");
-        user_name_str = user_name
-            .ok_or(Error::from("User has no name or login!"))?
-            .as_str()
-            .ok_or(Error::from("Failed to parse user info login!"))?
-            .to_owned();
-    }
+    let blogs = sql::get_blogs(sql_ctx, config.auto_close_days, config.auto_close_from)?;
 
-    let user_id: u64 = user_info
-        .get("id")
-        .ok_or(Error::from("Failed to parse user info id!"))?
-        .to_string()
-        .parse()?;
+    let json: String = serde_json::to_string(&blogs)?;
 
-    let user_login: String = user_info
-        .get("login")
-        .ok_or(Error::from("Failed to parse user info login!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info login!"))?
-        .to_string();
+    res.body(json);
 
-    let user_url = user_info
-        .get("html_url")
-        .ok_or(Error::from("Failed to parse user info profile url!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info profile url!"))?;
+    Ok(())
+}
 
-    let user_avatar_url = user_info
-        .get("avatar_url")
-        .ok_or(Error::from("Failed to parse user info profile avatar url!"))?
-        .as_str()
-        .ok_or(Error::from("Failed to parse user info profile avatar url!"))?;
+#[handler]
+async fn admin_set_blog(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
+    let config: &Config = depot.obtain().unwrap();
 
-    let is_admin: bool =
-        config.admins.iter().fold(
-            false,
-            |acc, elem| if acc { acc } else { elem == &user_login },
-        );
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
 
-    let can_del: bool =
-        sql::check_edit_comment_auth(sql_ctx.clone(), &comment_id, &user_id.to_string())?;
-    if !can_del && !is_admin {
-        eprintln!(
-            "User tried to delete comment they didn't make! {}",
-            &comment_id
-        );
-        res.status_code(StatusCode::BAD_REQUEST);
-        res.body(format!(
-            r#"<html><head><style>{}</style></head><body>
-            <b>Bad Request</b><br>
-            <p>You are not the commentor of the comment you are trying to edit.</p>
-            </body></html>"#,
-            COMMON_CSS,
-        ));
-        return Ok(());
+    let req_login_id = json_get_str(&request_json, "login_id")?;
+    let req_blog_id = json_get_str(&request_json, "blog_id")?;
+    let req_url: Option<&str> = request_json.get("url").and_then(|val| val.as_str());
+    let req_title: Option<&str> = request_json.get("title").and_then(|val| val.as_str());
+    let req_open: bool = request_json
+        .get("open")
+        .and_then(|val| val.as_bool())
+        .unwrap_or(true);
+
+    let req_author: Option<&str> = request_json.get("author").and_then(|val| val.as_str());
+
+    let req_post_date: Option<time::OffsetDateTime> = request_json
+        .get("post_date")
+        .and_then(|val| val.as_str())
+        .map(|val| time::OffsetDateTime::parse(val, &time::format_description::well_known::Rfc3339))
+        .transpose()
+        .map_err(|_| Error::from("post_date is not an RFC 3339 date!").into_client_err())?;
+
+    if req_blog_id.is_empty() {
+        return Err(Error::from("Blog id is empty!").into_client_err());
     }
 
-    if is_admin {
-        sql::try_delete_comment_id_only(sql_ctx.clone(), &comment_id)?;
-    } else {
-        sql::try_delete_comment(sql_ctx.clone(), &comment_id, user_id)?;
-    }
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
 
-    let login_id = sql::add_login(
-        sql_ctx.clone(),
-        if config.x_real_ip_enabled {
-            req.header("x-real-ip")
-        } else {
-            None
-        },
-        user_id,
-        &user_name_str,
-        &user_login,
-        user_url,
-        user_avatar_url,
+    let login = get_admin_login(req, config, sql_ctx.clone(), req_login_id)?;
+
+    sql::set_blog(
+        sql_ctx,
+        req_blog_id,
+        req_url,
+        req_title,
+        req_open,
+        req_post_date,
+        req_author,
     )?;
 
-    let script = format!(
-        r#"
-            "use strict";
-            setTimeout(() => {{
-                window.localStorage.setItem("seodisp_comments_login_id", "{}");
-                window.location = "{}";
-            }}, 5000);
-        "#,
-        login_id, blog_url
+    // The author's badges may have changed.
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(req_blog_id);
+
+    println!(
+        "Admin \"{}\" set blog \"{}\" (open: {})",
+        &login.userlogin, req_blog_id, req_open
     );
-    res.body(format!(
-        r#"<html><head><style>{}</style></head><body>
-        <b>Attempted Comment Delete, reloading blog url...</b>
-        <script>
-        {}
-        </script>
-        </body></html>"#,
-        COMMON_CSS, script
-    ));
 
     Ok(())
 }
 
 #[handler]
-async fn get_comments_by_blog_id(
-    req: &mut Request,
-    res: &mut Response,
-    depot: &mut Depot,
-) -> Result<(), Error> {
+async fn admin_remove_blog(req: &mut Request, depot: &mut Depot) -> Result<(), Error> {
     let config: &Config = depot.obtain().unwrap();
 
-    let blog_id: String = req.try_query("blog_id").map_err(Error::err_to_client_err)?;
+    let request_json: serde_json::Value =
+        req.parse_json().await.map_err(Error::err_to_client_err)?;
 
-    let comments = sql::get_comments_per_blog_id(config.into(), &blog_id)?;
+    let req_login_id = json_get_str(&request_json, "login_id")?;
+    let req_blog_id = json_get_str(&request_json, "blog_id")?;
 
-    let json: String = serde_json::to_string(&comments)?;
+    let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
 
-    res.body(json);
+    let login = get_admin_login(req, config, sql_ctx.clone(), req_login_id)?;
+
+    if !sql::remove_blog(sql_ctx, req_blog_id)? {
+        return Err(Error::coded(
+            ErrorCode::BlogNotFound,
+            "Blog does not exist!",
+        ));
+    }
+
+    // The cached comments have the removed author's badges.
+    let cache: &Arc<CommentCache> = depot.obtain().unwrap();
+    cache.invalidate(req_blog_id);
+
+    println!(
+        "Admin \"{}\" removed blog \"{}\"",
+        &login.userlogin, req_blog_id
+    );
 
     Ok(())
 }
@@ -1335,17 +2387,9 @@ async fn logout(req: &mut Request, res: &mut Response, depot: &mut Depot) -> Res
 
     let sql_ctx: SQLCtx = SQLCtx::new_as_connection(config)?;
 
-    sql::cleanup_logins(sql_ctx.clone(), config.login_timeout_minutes)?;
-
     let login_id: Result<String, _> = req.try_query("login_id");
 
-    let client_ip = if config.get_x_real_ip_enabled()
-        && let Some(real_ip) = req.header("x-real-ip")
-    {
-        real_ip
-    } else {
-        req.remote_addr().to_string()
-    };
+    let client_ip: String = get_client_ip(req, config);
 
     if let Ok(login_id) = login_id {
         sql::logout(sql_ctx.clone(), &login_id, &client_ip).map_err(Error::err_to_client_err)?;
@@ -1394,20 +2438,88 @@ async fn main() {
         base_url: config.get_base_url().to_owned(),
         allowed_urls: config.get_allowed_urls().to_vec(),
         allowed_bids: config.get_allowed_bids().to_vec(),
+        blog_url_template: config.get_blog_url_template().map(str::to_owned),
+        auto_close_days: config.get_auto_close_days(),
+        auto_close_from: config.get_auto_close_from(),
+        max_reply_depth: config.get_max_reply_depth(),
+        comment_counts_max_age_seconds: config.get_comment_counts_max_age(),
+        comments_cache_control: config.get_comments_cache_control().to_owned(),
+        permalink_fragment: config.get_permalink_fragment().to_owned(),
+        comment_limits: config.get_comment_limits(),
+        max_body_size: config.get_max_body_size(),
+        check_confusable_names: config.get_check_confusable_names(),
+        reactions: config.get_reactions().to_vec(),
+        events_heartbeat_seconds: config.get_events_heartbeat_seconds(),
+        events_relay: config.get_events_relay(),
+        events_relay_interval_ms: config.get_events_relay_interval_ms(),
+        public_search: config.get_public_search(),
         user_agent: config.get_user_agent().to_owned(),
         on_comment_cmds: config.get_on_comment_cmds().to_vec(),
         admins: config.get_admins().to_vec(),
+        moderators: config.get_moderators().to_vec(),
         login_timeout_minutes: config.get_login_timeout(),
         x_real_ip_enabled: config.get_x_real_ip_enabled(),
+        maintenance_interval_seconds: config.get_maintenance_interval(),
+        hide_deleted_comments: config.get_hide_deleted_comments(),
+        deleted_retention_days: config.get_deleted_retention_days(),
+        public_revision_history: config.get_public_revision_history(),
+        timestamp_migration_offset: config.get_timestamp_migration_offset().map(str::to_owned),
     };
 
     sql::set_up_sql_db((&salvo_conf).into(), &salvo_conf).unwrap();
 
+    let imported_count = sql::import_blogs(
+        (&salvo_conf).into(),
+        &allowlist::get_literals(&salvo_conf.allowed_bids),
+    )
+    .unwrap();
+    if imported_count != 0 {
+        println!("Imported {} blog id(s) from the config.", imported_count);
+    }
+
+    let comment_cache: Arc<CommentCache> = Arc::new(CommentCache::new(
+        config.get_comment_cache_size(),
+        Duration::from_secs(config.get_comment_cache_ttl()),
+    ));
+
+    let renderer: Arc<Renderer> = Arc::new(Renderer::new(config.get_markdown_extensions()));
+
+    let (stop_sender, stop_receiver) = tokio::sync::watch::channel(false);
+
+    let event_hub: Arc<EventHub> =
+        Arc::new(EventHub::new(&salvo_conf, stop_receiver.clone()).unwrap());
+    let relay_handle = if salvo_conf.events_relay {
+        Some(tokio::spawn(events::run_relay(
+            salvo_conf.clone(),
+            event_hub.clone(),
+            stop_receiver.clone(),
+        )))
+    } else {
+        None
+    };
+
+    let maintenance_handle = tokio::spawn(maintenance::run(
+        salvo_conf.clone(),
+        comment_cache.clone(),
+        stop_receiver,
+    ));
+
     let router = Router::new()
-        .hoop(affix_state::inject(salvo_conf))
+        .hoop(
+            affix_state::inject(salvo_conf)
+                .inject(comment_cache)
+                .inject(renderer)
+                .inject(event_hub),
+        )
+        .hoop(check_body_size)
         .get(root_handler)
         .push(Router::with_path("get_comment").get(comment_text_get))
+        .push(Router::with_path("get_comment_info").get(comment_info_get))
         .push(Router::with_path("get_comments").get(get_comments_by_blog_id))
+        .push(Router::with_path("get_comment_counts").get(get_comment_counts))
+        .push(Router::with_path("comment_events").get(comment_events))
+        .push(Router::with_path("search_comments").get(search_comments))
+        .push(Router::with_path("get_comment_revisions").get(get_comment_revisions))
         .push(Router::with_path("do_comment").get(login_to_comment))
         .push(Router::with_path("github_auth_make_comment").get(github_auth_make_comment))
         .push(Router::with_path("submit_comment").post(submit_comment))
@@ -1416,7 +2528,19 @@ async fn main() {
         .push(Router::with_path("submit_edit_comment").post(submit_edit_comment))
         .push(Router::with_path("del_comment").get(login_to_delete_comment))
         .push(Router::with_path("github_auth_del_comment").get(github_auth_del_comment))
-        .push(Router::with_path("logout").get(logout));
+        .push(Router::with_path("logout").get(logout))
+        .push(Router::with_path("add_reaction").post(add_reaction))
+        .push(Router::with_path("remove_reaction").post(remove_reaction))
+        .push(Router::with_path("admin/deleted_comments").get(admin_get_deleted_comments))
+        .push(Router::with_path("admin/restore_comment").post(admin_restore_comment))
+        .push(Router::with_path("admin/pin_comment").post(admin_pin_comment))
+        .push(Router::with_path("admin/blogs").get(admin_get_blogs))
+        .push(Router::with_path("admin/set_blog").post(admin_set_blog))
+        .push(Router::with_path("admin/remove_blog").post(admin_remove_blog))
+        .push(Router::with_path("admin/cache_stats").get(admin_get_cache_stats))
+        .push(api_v1::router());
+
+    salvo::http::request::set_global_secure_max_size(config.get_max_body_size());
 
     let listener = TcpListener::new(format!("{}:{}", config.get_addr(), config.get_port()));
 
@@ -1425,6 +2549,7 @@ async fn main() {
     tokio::spawn(async move {
         loop {
             if signal::SIGNAL_HANDLED.load(std::sync::atomic::Ordering::Relaxed) {
+                let _ = stop_sender.send(true);
                 handle.stop_graceful(Duration::from_secs(5));
                 break;
             }
@@ -1432,4 +2557,13 @@ async fn main() {
         }
     });
     server.serve(router).await;
+
+    if let Err(e) = maintenance_handle.await {
+        eprintln!("Maintenance task failed to stop cleanly: {}", e);
+    }
+    if let Some(relay_handle) = relay_handle
+        && let Err(e) = relay_handle.await
+    {
+        eprintln!("Event relay task failed to stop cleanly: {}", e);
+    }
 }
@@ -0,0 +1,88 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::watch;
+
+use crate::{Config, comment_cache::CommentCache, sql};
+
+// Other instances only need the events until their next poll.
+const COMMENT_EVENT_RETENTION_MINUTES: u64 = 60;
+
+pub async fn run(config: Config, cache: Arc<CommentCache>, mut stop: watch::Receiver<bool>) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(config.get_maintenance_interval()));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let config = config.clone();
+                let cache = cache.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || run_once(&config, &cache)).await {
+                    eprintln!("Maintenance: Task panicked: {}", e);
+                }
+            }
+            _ = stop.changed() => {
+                break;
+            }
+        }
+    }
+
+    println!("Maintenance: Stopped.");
+}
+
+fn run_once(config: &Config, cache: &CommentCache) {
+    let sql_ctx = match sql::SQLCtx::new_as_connection(config) {
+        Ok(sql_ctx) => sql_ctx,
+        Err(e) => {
+            eprintln!("Maintenance: Failed to connect to db: {}", e);
+            return;
+        }
+    };
+
+    match sql::cleanup_pending_actions(sql_ctx.clone()) {
+        Ok(0) => (),
+        Ok(count) => println!("Maintenance: Removed {} expired pending action(s).", count),
+        Err(e) => eprintln!("Maintenance: Failed to clean up pending actions: {}", e),
+    }
+
+    match sql::cleanup_logins(sql_ctx.clone(), config.get_login_timeout()) {
+        Ok(0) => (),
+        Ok(count) => println!("Maintenance: Removed {} expired login(s).", count),
+        Err(e) => eprintln!("Maintenance: Failed to clean up logins: {}", e),
+    }
+
+    if config.events_relay {
+        match sql::cleanup_comment_events(sql_ctx.clone(), COMMENT_EVENT_RETENTION_MINUTES) {
+            Ok(0) => (),
+            Ok(count) => println!("Maintenance: Removed {} old comment event(s).", count),
+            Err(e) => eprintln!("Maintenance: Failed to clean up comment events: {}", e),
+        }
+    }
+
+    if config.get_deleted_retention_days() != 0 {
+        match sql::purge_deleted_comments(sql_ctx.clone(), config.get_deleted_retention_days()) {
+            Ok(0) => (),
+            Ok(count) => {
+                println!("Maintenance: Purged {} deleted comment(s).", count);
+                cache.clear();
+            }
+            Err(e) => eprintln!("Maintenance: Failed to purge deleted comments: {}", e),
+        }
+    }
+}
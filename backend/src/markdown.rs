@@ -0,0 +1,247 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use ammonia::UrlRelative;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd, html};
+
+use crate::{config::MarkdownExtension, sql::Comment};
+
+// Everything rendered is dropped once this many comments are kept.
+const MAX_RENDERED_COMMENTS: usize = 10000;
+
+const ALLOWED_TAGS: &[&str] = &[
+    "a",
+    "blockquote",
+    "br",
+    "code",
+    "del",
+    "em",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "input",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "strong",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+// Renders the Markdown of comments into sanitized html, keeping the result
+// for each comment until its edit count changes.
+pub struct Renderer {
+    options: Options,
+    sanitizer: ammonia::Builder<'static>,
+    // Keyed by comment id, with the edit count the html was rendered from.
+    rendered: Mutex<HashMap<String, (u64, String)>>,
+}
+
+impl Renderer {
+    pub fn new(extensions: &[MarkdownExtension]) -> Self {
+        let mut options: Options = Options::empty();
+        for extension in extensions {
+            options.insert(match extension {
+                MarkdownExtension::Strikethrough => Options::ENABLE_STRIKETHROUGH,
+                MarkdownExtension::Tables => Options::ENABLE_TABLES,
+                MarkdownExtension::Tasklists => Options::ENABLE_TASKLISTS,
+            });
+        }
+
+        let mut sanitizer = ammonia::Builder::empty();
+        sanitizer
+            .add_tags(ALLOWED_TAGS)
+            .add_tag_attributes("a", &["href"])
+            .add_tag_attributes("ol", &["start"])
+            .add_tag_attributes("input", &["type", "checked", "disabled"])
+            .add_tag_attributes("th", &["style"])
+            .add_tag_attributes("td", &["style"])
+            .url_schemes(HashSet::from(["http", "https", "mailto"]))
+            .url_relative(UrlRelative::Deny)
+            .link_rel(Some("nofollow ugc noopener noreferrer"))
+            .attribute_filter(filter_attribute);
+
+        Renderer {
+            options,
+            sanitizer,
+            rendered: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, (u64, String)>> {
+        // The map is valid even if a holder panicked.
+        self.rendered
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn render(&self, markdown: &str) -> String {
+        let events = Parser::new_ext(markdown, self.options).map(|event| match event {
+            // Raw html is shown as it was typed.
+            Event::Start(Tag::HtmlBlock) => Event::Start(Tag::Paragraph),
+            Event::End(TagEnd::HtmlBlock) => Event::End(TagEnd::Paragraph),
+            Event::Html(text) | Event::InlineHtml(text) => Event::Text(text),
+            // Comments used to be plain text, so their line breaks are kept.
+            Event::SoftBreak => Event::HardBreak,
+            event => event,
+        });
+
+        let mut unsanitized: String = String::new();
+        html::push_html(&mut unsanitized, events);
+
+        self.sanitizer.clean(&unsanitized).to_string()
+    }
+
+    // Sets comment_html of each comment.
+    pub fn render_comments(&self, comments: &mut [Comment]) {
+        let mut rendered = self.lock();
+
+        for comment in comments.iter_mut() {
+            if comment.deleted {
+                comment.comment_html = ammonia::clean_text(&comment.comment);
+                continue;
+            }
+
+            let cached: Option<String> = rendered
+                .get(&comment.comment_id)
+                .filter(|(edit_count, _)| *edit_count == comment.edit_count)
+                .map(|(_, html)| html.clone());
+            if let Some(html) = cached {
+                comment.comment_html = html;
+                continue;
+            }
+
+            let html: String = self.render(&comment.comment);
+            if rendered.len() >= MAX_RENDERED_COMMENTS
+                && !rendered.contains_key(&comment.comment_id)
+            {
+                rendered.clear();
+            }
+            rendered.insert(
+                comment.comment_id.clone(),
+                (comment.edit_count, html.clone()),
+            );
+            comment.comment_html = html;
+        }
+    }
+
+    pub fn render_comment(&self, comment: &mut Comment) {
+        self.render_comments(std::slice::from_mut(comment));
+    }
+}
+
+fn filter_attribute<'u>(element: &str, attribute: &str, value: &'u str) -> Option<Cow<'u, str>> {
+    match (element, attribute) {
+        // Only the checkboxes of task lists.
+        ("input", "type") if value != "checkbox" => None,
+        // Only the column alignment of tables.
+        ("th" | "td", "style")
+            if !matches!(
+                value,
+                "text-align: left" | "text-align: center" | "text-align: right"
+            ) =>
+        {
+            None
+        }
+        _ => Some(Cow::Borrowed(value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer() -> Renderer {
+        Renderer::new(&[
+            MarkdownExtension::Strikethrough,
+            MarkdownExtension::Tables,
+            MarkdownExtension::Tasklists,
+        ])
+    }
+
+    #[test]
+    fn raw_html_is_text() {
+        let html: String = renderer().render("<script>alert(1)</script>");
+        assert!(!html.contains("<script"));
+        assert!(html.contains("&lt;script&gt;"));
+
+        let html: String = renderer().render("Some <b>bold</b> text");
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
+    }
+
+    #[test]
+    fn only_absolute_safe_links() {
+        let html: String = renderer().render("[link](https://example.com/)");
+        assert!(html.contains(r#"href="https://example.com/""#));
+        assert!(html.contains(r#"rel="nofollow ugc noopener noreferrer""#));
+
+        let html: String = renderer().render("[link](javascript:alert(1))");
+        assert!(!html.contains("href"));
+        assert!(!html.contains("javascript"));
+
+        let html: String = renderer().render("[link](/relative/path)");
+        assert!(!html.contains("href"));
+    }
+
+    #[test]
+    fn task_list_checkboxes() {
+        let html: String = renderer().render("- [x] done\n- [ ] not done");
+        assert!(html.contains(r#"type="checkbox""#));
+
+        assert_eq!(
+            filter_attribute("input", "type", "checkbox").as_deref(),
+            Some("checkbox")
+        );
+        assert_eq!(filter_attribute("input", "type", "text"), None);
+        assert_eq!(filter_attribute("input", "type", "submit"), None);
+    }
+
+    #[test]
+    fn table_alignment() {
+        let html: String = renderer().render("| a | b |\n|:-:|--:|\n| 1 | 2 |");
+        assert!(html.contains(r#"style="text-align: center""#));
+        assert!(html.contains(r#"style="text-align: right""#));
+
+        for element in ["th", "td"] {
+            assert_eq!(
+                filter_attribute(element, "style", "text-align: left").as_deref(),
+                Some("text-align: left")
+            );
+            assert_eq!(filter_attribute(element, "style", "color: red"), None);
+            assert_eq!(
+                filter_attribute(element, "style", "text-align: left; color: red"),
+                None
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Config,
+    error::{Error, ErrorCode},
+    sql::{self, Comment, SQLCtx},
+};
+
+pub const MAX_LIMIT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    Oldest,
+    Newest,
+    // Most reactions first.
+    Top,
+}
+
+impl Order {
+    pub fn parse(val: &str) -> Result<Self, Error> {
+        match val {
+            "oldest" => Ok(Order::Oldest),
+            "newest" => Ok(Order::Newest),
+            "top" => Ok(Order::Top),
+            _ => Err(
+                Error::from("order must be \"oldest\", \"newest\" or \"top\"!").into_client_err(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Page<T> {
+    pub comments: T,
+    pub next_cursor: Option<String>,
+    pub total: u64,
+    pub closed: bool,
+}
+
+// The position of a thread, from its top-level comment. Pinned threads come
+// first in any order, and threads with as many reactions are oldest first in
+// "top" order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    pub create_date_ms: i64,
+    pub comment_id: String,
+    pub reactions: u64,
+    pub pinned: bool,
+}
+
+impl Position {
+    fn encode(&self) -> String {
+        format!(
+            "{}:{}:{}:{}",
+            u8::from(self.pinned),
+            self.reactions,
+            self.create_date_ms,
+            self.comment_id
+        )
+        .bytes()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+    }
+
+    fn decode(cursor: &str) -> Result<Self, Error> {
+        let invalid = || Error::coded(ErrorCode::BadRequest, "Invalid cursor!");
+
+        if cursor.len() % 2 != 0 || !cursor.is_ascii() {
+            return Err(invalid());
+        }
+        let bytes: Vec<u8> = (0..cursor.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&cursor[idx..idx + 2], 16))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid())?;
+        let decoded = String::from_utf8(bytes).map_err(|_| invalid())?;
+        let parts: Vec<&str> = decoded.split(':').collect();
+        let [pinned, reactions, create_date_ms, comment_id] = parts[..] else {
+            return Err(invalid());
+        };
+
+        Ok(Position {
+            create_date_ms: create_date_ms.parse().map_err(|_| invalid())?,
+            comment_id: comment_id.to_owned(),
+            reactions: reactions.parse().map_err(|_| invalid())?,
+            pinned: match pinned {
+                "0" => false,
+                "1" => true,
+                _ => return Err(invalid()),
+            },
+        })
+    }
+}
+
+// Pages through threads, so a top-level comment is always returned with all
+// of its replies. Returns the page's comments as arranged by
+// "thread::arrange", the cursor of the next page, and the total number of
+// threads.
+pub fn paginate(
+    config: &Config,
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    order: Order,
+    limit: usize,
+    cursor: Option<&str>,
+) -> Result<(Vec<Comment>, Option<String>, u64), Error> {
+    let after: Option<Position> = cursor.map(Position::decode).transpose()?;
+
+    let (comments, last, total) = sql::get_comment_page(
+        sql_ctx,
+        blog_id,
+        config.hide_deleted_comments,
+        &config.reactions,
+        order,
+        after.as_ref(),
+        limit,
+    )?;
+
+    Ok((comments, last.map(|position| position.encode()), total))
+}
@@ -0,0 +1,211 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+use crate::{
+    error::{Error, ErrorCode},
+    sql::{self, SQLCtx},
+};
+
+const MAX_LIMIT: u64 = 100;
+const DEFAULT_LIMIT: u64 = 20;
+const MAX_QUERY_CHARS: usize = 256;
+const MAX_TERMS: usize = 16;
+const SNIPPET_CHARS: usize = 200;
+// Shown before the first match in a snippet.
+const SNIPPET_LEAD_CHARS: usize = 60;
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    q: Option<String>,
+    author: Option<String>,
+    blog_id: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    limit: Option<u64>,
+    offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchResult {
+    pub comment_id: String,
+    pub blog_id: String,
+    pub username: String,
+    pub userurl: String,
+    pub useravatar: String,
+    pub create_date: String,
+    pub create_date_ms: i64,
+    // Html with the search terms in <mark>.
+    pub snippet: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    pub total: u64,
+    pub next_offset: Option<u64>,
+}
+
+fn non_empty(val: &Option<String>) -> Option<&str> {
+    val.as_deref().map(str::trim).filter(|val| !val.is_empty())
+}
+
+fn parse_date(val: &Option<String>, name: &str) -> Result<Option<OffsetDateTime>, Error> {
+    non_empty(val)
+        .map(|val| OffsetDateTime::parse(val, &Rfc3339))
+        .transpose()
+        .map_err(|_| {
+            Error::coded(
+                ErrorCode::BadRequest,
+                format!("{} is not an RFC 3339 date!", name),
+            )
+        })
+}
+
+pub fn run(sql_ctx: SQLCtx, query: &SearchQuery) -> Result<SearchPage, Error> {
+    let limit: u64 = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(Error::coded(
+            ErrorCode::BadRequest,
+            format!("limit must be between 1 and {}!", MAX_LIMIT),
+        ));
+    }
+    let offset: u64 = query.offset.unwrap_or(0);
+
+    let text: Option<&str> = non_empty(&query.q);
+    if let Some(text) = text
+        && text.chars().count() > MAX_QUERY_CHARS
+    {
+        return Err(Error::coded(
+            ErrorCode::BadRequest,
+            format!("q must be at most {} characters!", MAX_QUERY_CHARS),
+        ));
+    }
+
+    let filter = sql::SearchFilter {
+        text,
+        author: non_empty(&query.author),
+        blog_id: non_empty(&query.blog_id),
+        from: parse_date(&query.from, "from")?,
+        to: parse_date(&query.to, "to")?,
+    };
+
+    let (hits, total) = sql::search_comments(sql_ctx, &filter, limit, offset)?;
+
+    let terms: Vec<Vec<char>> = text.map(split_terms).unwrap_or_default();
+    let results: Vec<SearchResult> = hits
+        .into_iter()
+        .map(|hit| SearchResult {
+            snippet: make_snippet(&hit.comment, &terms),
+            comment_id: hit.comment_id,
+            blog_id: hit.blog_id,
+            username: hit.username,
+            userurl: hit.userurl,
+            useravatar: hit.useravatar,
+            create_date: hit.create_date,
+            create_date_ms: hit.create_date_ms,
+        })
+        .collect();
+
+    let end: u64 = offset + results.len() as u64;
+    Ok(SearchPage {
+        next_offset: if end < total { Some(end) } else { None },
+        results,
+        total,
+    })
+}
+
+// Only the first char of a lowercase mapping is kept, so that positions match
+// the original text.
+fn lowercase(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+fn split_terms(text: &str) -> Vec<Vec<char>> {
+    let mut terms: Vec<Vec<char>> = Vec::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let term: Vec<char> = word.chars().map(lowercase).collect();
+        if !term.is_empty() && !terms.contains(&term) && terms.len() < MAX_TERMS {
+            terms.push(term);
+        }
+    }
+    terms
+}
+
+// Marks the chars of terms found at the start of a word.
+fn find_terms(chars: &[char], terms: &[Vec<char>]) -> Vec<bool> {
+    let lower: Vec<char> = chars.iter().copied().map(lowercase).collect();
+    let mut marked: Vec<bool> = vec![false; chars.len()];
+    for start in 0..lower.len() {
+        if start > 0 && lower[start - 1].is_alphanumeric() {
+            continue;
+        }
+        for term in terms {
+            if lower[start..].starts_with(term) {
+                marked[start..start + term.len()].fill(true);
+            }
+        }
+    }
+    marked
+}
+
+fn push_escaped(html: &mut String, c: char) {
+    match c {
+        '&' => html.push_str("&amp;"),
+        '<' => html.push_str("&lt;"),
+        '>' => html.push_str("&gt;"),
+        '"' => html.push_str("&quot;"),
+        '\'' => html.push_str("&#39;"),
+        c if c.is_whitespace() => html.push(' '),
+        c => html.push(c),
+    }
+}
+
+// Returns part of the comment starting a bit before the first term, as html.
+fn make_snippet(comment: &str, terms: &[Vec<char>]) -> String {
+    let chars: Vec<char> = comment.chars().collect();
+    let marked: Vec<bool> = find_terms(&chars, terms);
+
+    let start: usize = marked
+        .iter()
+        .position(|is_marked| *is_marked)
+        .map(|first| first.saturating_sub(SNIPPET_LEAD_CHARS))
+        .unwrap_or(0);
+    let end: usize = (start + SNIPPET_CHARS).min(chars.len());
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut in_mark: bool = false;
+    for (c, is_marked) in chars[start..end].iter().zip(&marked[start..end]) {
+        if *is_marked != in_mark {
+            snippet.push_str(if *is_marked { "<mark>" } else { "</mark>" });
+            in_mark = *is_marked;
+        }
+        push_escaped(&mut snippet, *c);
+    }
+    if in_mark {
+        snippet.push_str("</mark>");
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+
+    snippet
+}
@@ -14,12 +14,26 @@
 // OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
 // PERFORMANCE OF THIS SOFTWARE.
 
-use std::sync::{Arc, Mutex};
-
-use crate::{Config, error::Error};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    Config,
+    config::AutoCloseFrom,
+    error::{Error, ErrorCode},
+    from_row::{DB_DATETIME_FORMAT, FromRow, db_row, get_column, report_bad_row},
+    pagination::{Order, Position},
+    thread,
+};
 use msql_ffi::{MSQLParamsWrapper, MSQLWrapper};
 use serde::Serialize;
-use time::{PrimitiveDateTime, UtcOffset, format_description};
+use time::{OffsetDateTime, format_description::well_known::Rfc3339};
+
+const PENDING_ACTION_TIMEOUT_MINUTES: u64 = 60;
+const DELETED_PLACEHOLDER: &str = "[deleted]";
+const META_TIMESTAMPS_UTC: &str = "timestamps_utc";
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 pub struct Comment {
@@ -28,29 +42,274 @@ pub struct Comment {
     pub userurl: String,
     pub useravatar: String,
     pub create_date: String,
+    pub create_date_ms: i64,
     pub edit_date: String,
+    pub edit_date_ms: i64,
+    pub comment: String,
+    pub comment_html: String,
+    pub deleted: bool,
+    pub edit_count: u64,
+    pub parent_id: Option<String>,
+    pub depth: u64,
+    pub reactions: Vec<Reaction>,
+    pub pinned: bool,
+    // Badges of the commenter.
+    pub admin: bool,
+    pub moderator: bool,
+    pub author: bool,
+}
+
+// "reacted" is whether the viewer made one of the reactions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reaction {
+    pub emoji: String,
+    pub count: u64,
+    pub reacted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommentCount {
+    pub count: u64,
+    pub latest_comment_date: Option<String>,
+    pub latest_comment_date_ms: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommentRevision {
+    pub revision: u64,
+    pub date: String,
+    pub date_ms: i64,
     pub comment: String,
 }
 
+// How a comment was deleted, for showing its status.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct PseudoComment {
-    pub user_id: u64,
+pub struct CommentModeration {
+    pub userurl: String,
+    pub modified_date: OffsetDateTime,
+    pub deleted_date: Option<String>,
+    pub deleted_date_ms: Option<i64>,
+    pub deleted_by: Option<String>,
+    pub delete_reason: Option<String>,
+}
+
+// Every given filter must match.
+#[derive(Debug, Clone, Default)]
+pub struct SearchFilter<'a> {
+    pub text: Option<&'a str>,
+    // A username or github login.
+    pub author: Option<&'a str>,
+    pub blog_id: Option<&'a str>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHit {
+    pub comment_id: String,
+    pub blog_id: String,
     pub username: String,
     pub userurl: String,
     pub useravatar: String,
-    pub blog_post_id: String,
+    pub create_date: String,
+    pub create_date_ms: i64,
+    pub comment: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DeletedComment {
     pub comment_id: String,
+    pub blog_id: String,
+    pub username: String,
+    pub userurl: String,
+    pub create_date: String,
+    pub deleted_date: String,
+    pub deleted_by: String,
+    pub delete_reason: Option<String>,
+    pub comment: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Blog {
+    pub blog_id: String,
+    pub url: Option<String>,
+    pub title: Option<String>,
+    pub open: bool,
+    pub closed: bool,
+    pub create_date: String,
+    pub create_date_ms: i64,
+    pub post_date: Option<String>,
+    pub post_date_ms: Option<i64>,
+    pub first_comment_date: Option<String>,
+    pub first_comment_date_ms: Option<i64>,
+    pub author: Option<String>,
+}
+
+// Changes whenever a comment of a blog is created, edited, deleted, restored,
+// pinned or purged, or gets a reaction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CommentsVersion {
+    pub version: u64,
+    pub last_modified: Option<OffsetDateTime>,
+    // The author of the blog, as it changes the badges.
+    pub author: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct LoginInfo {
-    pub user_id: String,
-    pub user_ip: Option<String>,
-    pub user_github_id: u64,
+pub struct PseudoComment {
+    pub user_id: u64,
     pub username: String,
-    pub userlogin: String,
     pub userurl: String,
     pub useravatar: String,
+    pub blog_post_id: String,
+    pub comment_id: String,
+    pub parent_id: Option<String>,
+}
+
+db_row! {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct LoginInfo {
+        pub user_id: String,
+        pub user_ip: Option<String>,
+        pub user_github_id: u64,
+        pub username: String,
+        pub userlogin: String,
+        pub userurl: String,
+        pub useravatar: String,
+    }
+}
+
+db_row! {
+    struct CommentRow {
+        uuid: String,
+        username: String,
+        userurl: String,
+        useravatar: String,
+        creation_date: OffsetDateTime,
+        edit_date: OffsetDateTime,
+        comment: String,
+        deleted_at: Option<OffsetDateTime>,
+        edit_count: u64,
+        parent_id: Option<String>,
+        pinned: bool,
+    }
+}
+
+db_row! {
+    struct ThreadPositionRow {
+        uuid: String,
+        creation_date: OffsetDateTime,
+        reactions: u64,
+        pinned: bool,
+    }
+}
+
+db_row! {
+    struct DeletedCommentRow {
+        uuid: String,
+        blog_post_id: Option<String>,
+        username: Option<String>,
+        userurl: Option<String>,
+        creation_date: OffsetDateTime,
+        deleted_at: OffsetDateTime,
+        deleted_by: Option<String>,
+        delete_reason: Option<String>,
+        comment: Option<String>,
+    }
+}
+
+db_row! {
+    struct CommentModerationRow {
+        userurl: String,
+        modified_date: OffsetDateTime,
+        deleted_at: Option<OffsetDateTime>,
+        deleted_by: Option<String>,
+        delete_reason: Option<String>,
+    }
+}
+
+db_row! {
+    struct SearchHitRow {
+        uuid: String,
+        blog_post_id: String,
+        username: String,
+        userurl: String,
+        useravatar: String,
+        creation_date: OffsetDateTime,
+        comment: String,
+    }
+}
+
+db_row! {
+    struct CommentCountRow {
+        blog_post_id: String,
+        count: u64,
+        latest_comment_date: OffsetDateTime,
+    }
+}
+
+db_row! {
+    struct RevisionRow {
+        date: OffsetDateTime,
+        comment: Option<String>,
+    }
+}
+
+db_row! {
+    struct BlogRow {
+        id: String,
+        url: Option<String>,
+        title: Option<String>,
+        is_open: bool,
+        creation_date: OffsetDateTime,
+        post_date: Option<OffsetDateTime>,
+        author: Option<String>,
+        first_comment_date: Option<OffsetDateTime>,
+    }
+}
+
+db_row! {
+    struct CommentsVersionRow {
+        version: u64,
+        last_modified: Option<OffsetDateTime>,
+        author: Option<String>,
+    }
+}
+
+db_row! {
+    struct ReactionCountRow {
+        comment_id: String,
+        emoji: String,
+        count: u64,
+    }
+}
+
+db_row! {
+    struct UserReactionRow {
+        comment_id: String,
+        emoji: String,
+    }
+}
+
+db_row! {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct CommentEvent {
+        pub id: u64,
+        pub blog_id: String,
+        pub kind: String,
+        pub comment_id: String,
+    }
+}
+
+db_row! {
+    struct PendingCreateRow {
+        user_id: u64,
+        username: String,
+        userurl: String,
+        useravatar: String,
+        target: String,
+        parent_id: Option<String>,
+    }
 }
 
 #[derive(Clone)]
@@ -65,16 +324,84 @@ pub enum SQLCtx {
     },
 }
 
+// Rolls back on drop unless committed.
+pub struct Transaction<'a> {
+    conn: &'a mut MSQLWrapper,
+    finished: bool,
+}
+
+impl<'a> Transaction<'a> {
+    pub fn begin(conn: &'a mut MSQLWrapper) -> Result<Self, Error> {
+        conn.query_drop("START TRANSACTION")?;
+
+        Ok(Transaction {
+            conn,
+            finished: false,
+        })
+    }
+
+    pub fn commit(mut self) -> Result<(), Error> {
+        // If this fails, dropping still rolls back.
+        self.conn.query_drop("COMMIT")?;
+        self.finished = true;
+
+        Ok(())
+    }
+
+    pub fn rollback(mut self) -> Result<(), Error> {
+        self.conn.query_drop("ROLLBACK")?;
+        self.finished = true;
+
+        Ok(())
+    }
+}
+
+impl std::ops::Deref for Transaction<'_> {
+    type Target = MSQLWrapper;
+
+    fn deref(&self) -> &Self::Target {
+        self.conn
+    }
+}
+
+impl std::ops::DerefMut for Transaction<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.conn
+    }
+}
+
+impl Drop for Transaction<'_> {
+    fn drop(&mut self) {
+        if !self.finished && self.conn.query_drop("ROLLBACK").is_err() {
+            eprintln!("Failed to roll back transaction!");
+        }
+    }
+}
+
+// All DATETIME values are stored and compared in UTC, regardless of the
+// timezone of the host or the db server.
+fn connect(addr: &str, port: u16, user: &str, pass: &str, db: &str) -> Result<MSQLWrapper, Error> {
+    let mut conn = MSQLWrapper::try_new(addr, port, user, pass, db)
+        .map_err(|_| -> Error { "Failed to create msql connection".into() })?;
+
+    conn.query_drop("SET time_zone = '+00:00'")?;
+
+    Ok(conn)
+}
+
+fn to_epoch_ms(date: OffsetDateTime) -> i64 {
+    (date.unix_timestamp_nanos() / 1_000_000) as i64
+}
+
 impl SQLCtx {
     pub fn new_as_connection(config: &Config) -> Result<Self, Error> {
-        MSQLWrapper::try_new(
+        connect(
             config.get_sql_addr(),
             config.get_sql_port(),
             config.get_sql_user(),
             config.get_sql_pass(),
             config.get_sql_db(),
         )
-        .map_err(|_| -> Error { "Failed to create msql connection".into() })
         .map(|w| SQLCtx::Connection(Arc::new(Mutex::new(w))))
     }
 }
@@ -109,9 +436,7 @@ impl TryFrom<SQLCtx> for Arc<Mutex<MSQLWrapper>> {
                 addr,
                 port,
                 db,
-            } => MSQLWrapper::try_new(&addr, port, &user, &pass, &db)
-                .map(|w| Arc::new(Mutex::new(w)))
-                .map_err(|_| -> Error { "Failed to create msql connection from Config".into() }),
+            } => connect(&addr, port, &user, &pass, &db).map(|w| Arc::new(Mutex::new(w))),
         }
     }
 }
@@ -125,8 +450,6 @@ pub fn set_up_sql_db(sql_ctx: SQLCtx, config: &Config) -> Result<(), Error> {
     conn.query_drop(
         r"CREATE TABLE IF NOT EXISTS COMMENT2 (
             uuid CHAR(36) PRIMARY KEY,
-            state CHAR(36),
-            INDEX state_index USING HASH (state),
             blog_post_id TINYTEXT,
             INDEX blog_post_id_index USING HASH (blog_post_id),
             user_id BIGINT,
@@ -137,11 +460,97 @@ pub fn set_up_sql_db(sql_ctx: SQLCtx, config: &Config) -> Result<(), Error> {
             creation_date DATETIME DEFAULT CURRENT_TIMESTAMP,
             INDEX creation_date_index USING BTREE (creation_date),
             edit_date DATETIME DEFAULT CURRENT_TIMESTAMP,
-            timeout_date DATETIME DEFAULT CURRENT_TIMESTAMP,
+            comment TEXT,
+            deleted_at DATETIME,
+            INDEX deleted_at_index USING BTREE (deleted_at),
+            deleted_by TINYTEXT,
+            delete_reason TEXT,
+            parent_id CHAR(36),
+            INDEX parent_id_index USING HASH (parent_id),
+            modified_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+            pinned BOOLEAN NOT NULL DEFAULT FALSE,
+            FULLTEXT INDEX comment_fulltext_index (comment)
+        )",
+    )?;
+
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS BLOG (
+            id VARCHAR(255) PRIMARY KEY,
+            url TEXT,
+            title TEXT,
+            is_open BOOLEAN NOT NULL DEFAULT TRUE,
+            creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            post_date DATETIME,
+            author TINYTEXT
+        )",
+    )?;
+
+    // The binary collation keeps emoji from comparing equal to each other.
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS REACTION (
+            comment_id CHAR(36) NOT NULL,
+            user_id BIGINT NOT NULL,
+            emoji VARCHAR(32) CHARACTER SET utf8mb4 COLLATE utf8mb4_bin NOT NULL,
+            creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (comment_id, user_id, emoji)
+        )",
+    )?;
+
+    // Bumped on every change to a blog's comments, see bump_version(). Rows are
+    // kept when a blog is removed, so that versions are never reused.
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS BLOG_VERSION (
+            blog_post_id VARCHAR(255) PRIMARY KEY,
+            version BIGINT UNSIGNED NOT NULL DEFAULT 0
+        )",
+    )?;
+
+    // Relays events between instances, see "events::run_relay".
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS COMMENT_EVENT (
+            id BIGINT AUTO_INCREMENT PRIMARY KEY,
+            blog_post_id TINYTEXT NOT NULL,
+            kind VARCHAR(16) NOT NULL,
+            comment_id CHAR(36) NOT NULL,
+            creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            INDEX creation_date_index USING BTREE (creation_date)
+        )",
+    )?;
+
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS META (
+            name VARCHAR(64) PRIMARY KEY,
+            value TEXT
+        )",
+    )?;
+
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS COMMENT_REVISION (
+            id BIGINT AUTO_INCREMENT PRIMARY KEY,
+            comment_id CHAR(36) NOT NULL,
+            INDEX comment_id_index USING HASH (comment_id),
+            revision_date DATETIME NOT NULL,
+            replaced_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
             comment TEXT
         )",
     )?;
 
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS PENDING_ACTION (
+            state CHAR(36) PRIMARY KEY,
+            action VARCHAR(16) NOT NULL,
+            target TINYTEXT NOT NULL,
+            parent_id CHAR(36),
+            user_id BIGINT,
+            username TINYTEXT,
+            userurl TINYTEXT,
+            useravatar TINYTEXT,
+            creation_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            expire_date DATETIME NOT NULL,
+            INDEX expire_date_index USING BTREE (expire_date)
+        )",
+    )?;
+
     conn.query_drop(
         r"CREATE TABLE IF NOT EXISTS LOGIN2 (
             id CHAR(36) PRIMARY KEY,
@@ -165,8 +574,8 @@ pub fn set_up_sql_db(sql_ctx: SQLCtx, config: &Config) -> Result<(), Error> {
         if rows_res.is_some() {
             // Migrate from COMMENT to COMMENT2 if exists.
             conn.query_drop(
-                r"INSERT INTO COMMENT2 (uuid, blog_post_id, user_id, username, userurl, useravatar, creation_date, edit_date, comment, timeout_date)
-                    SELECT uuid, blog_post_id, user_id, username, userurl, useravatar, creation_date, edit_date, comment, NULL FROM COMMENT
+                r"INSERT INTO COMMENT2 (uuid, blog_post_id, user_id, username, userurl, useravatar, creation_date, edit_date, comment)
+                    SELECT uuid, blog_post_id, user_id, username, userurl, useravatar, creation_date, edit_date, comment FROM COMMENT
                 "
             )?;
 
@@ -174,313 +583,2016 @@ pub fn set_up_sql_db(sql_ctx: SQLCtx, config: &Config) -> Result<(), Error> {
         }
     }
 
+    // Pending actions used to be placeholder rows in COMMENT2. They are
+    // temporary, so they are dropped instead of moved to PENDING_ACTION.
+    if has_column(&mut conn, config.get_sql_db(), "COMMENT2", "timeout_date")? {
+        conn.query_drop("DELETE FROM COMMENT2 WHERE timeout_date IS NOT NULL")?;
+        conn.query_drop("ALTER TABLE COMMENT2 DROP COLUMN timeout_date")?;
+    }
+    if has_column(&mut conn, config.get_sql_db(), "COMMENT2", "state")? {
+        conn.query_drop("ALTER TABLE COMMENT2 DROP COLUMN state")?;
+    }
+
+    if !has_column(&mut conn, config.get_sql_db(), "COMMENT2", "parent_id")? {
+        conn.query_drop(
+            r"ALTER TABLE COMMENT2
+                ADD COLUMN parent_id CHAR(36),
+                ADD INDEX parent_id_index USING HASH (parent_id)",
+        )?;
+    }
+
+    if !has_column(
+        &mut conn,
+        config.get_sql_db(),
+        "PENDING_ACTION",
+        "parent_id",
+    )? {
+        conn.query_drop("ALTER TABLE PENDING_ACTION ADD COLUMN parent_id CHAR(36)")?;
+    }
+
+    if !has_column(&mut conn, config.get_sql_db(), "BLOG", "post_date")? {
+        conn.query_drop("ALTER TABLE BLOG ADD COLUMN post_date DATETIME")?;
+    }
+
+    if !has_column(&mut conn, config.get_sql_db(), "COMMENT2", "deleted_at")? {
+        conn.query_drop(
+            r"ALTER TABLE COMMENT2
+                ADD COLUMN deleted_at DATETIME,
+                ADD INDEX deleted_at_index USING BTREE (deleted_at),
+                ADD COLUMN deleted_by TINYTEXT,
+                ADD COLUMN delete_reason TEXT",
+        )?;
+    }
+
+    // Updated by the db server on any change to the row, for HTTP caching.
+    if !has_column(&mut conn, config.get_sql_db(), "COMMENT2", "modified_date")? {
+        conn.query_drop(
+            "ALTER TABLE COMMENT2 ADD COLUMN modified_date DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP",
+        )?;
+    }
+
+    if !has_column(&mut conn, config.get_sql_db(), "COMMENT2", "pinned")? {
+        conn.query_drop("ALTER TABLE COMMENT2 ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE")?;
+    }
+
+    if !has_column(&mut conn, config.get_sql_db(), "BLOG", "author")? {
+        conn.query_drop("ALTER TABLE BLOG ADD COLUMN author TINYTEXT")?;
+    }
+
+    // Building the index can take a while on a large table.
+    if !has_index(
+        &mut conn,
+        config.get_sql_db(),
+        "COMMENT2",
+        "comment_fulltext_index",
+    )? {
+        println!("Building the full-text index of comments...");
+        conn.query_drop(
+            "ALTER TABLE COMMENT2 ADD FULLTEXT INDEX comment_fulltext_index (comment)",
+        )?;
+    }
+
+    let mut tx = Transaction::begin(&mut conn)?;
+    migrate_timestamps_to_utc(&mut tx, config)?;
+    tx.commit()?;
+
     // No need to migrate LOGIN to LOGIN2, because entries are temporary.
     conn.query_drop("DROP TABLE IF EXISTS LOGIN")?;
 
-    // Drop unused tables. The data in these tables were meant to be temporary
-    // so no migration is required for them.
-    conn.query_drop(r"DROP TABLE IF EXISTS PSEUDO_COMMENT")?;
-    conn.query_drop(r"DROP TABLE IF EXISTS GITHUB_RNG")?;
+    // Drop unused tables. The data in these tables were meant to be temporary
+    // so no migration is required for them.
+    conn.query_drop(r"DROP TABLE IF EXISTS PSEUDO_COMMENT")?;
+    conn.query_drop(r"DROP TABLE IF EXISTS GITHUB_RNG")?;
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingAction {
+    Create,
+    Edit,
+    Delete,
+    Login,
+}
+
+impl PendingAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PendingAction::Create => "create",
+            PendingAction::Edit => "edit",
+            PendingAction::Delete => "delete",
+            PendingAction::Login => "login",
+        }
+    }
+}
+
+// The rows changed by the last statement on this connection. The msql wrapper
+// doesn't return the count, so ROW_COUNT() is queried instead: the caller must
+// hold the connection's lock from the statement until this returns, and run
+// nothing else on it in between, as any statement resets the count.
+fn get_affected_rows(conn: &mut MSQLWrapper) -> Result<u64, Error> {
+    let rows = conn.query_with_params_rows("SELECT ROW_COUNT()", &MSQLParamsWrapper::new())?;
+
+    if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        let count: i64 = get_column(&rows[0], 0, "ROW_COUNT", "count")?;
+        Ok(count.max(0) as u64)
+    } else {
+        Err("Internal error querying affected row count".into())
+    }
+}
+
+fn has_column(conn: &mut MSQLWrapper, db: &str, table: &str, column: &str) -> Result<bool, Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(db)?;
+    params.append_str(table)?;
+    params.append_str(column)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT column_name FROM information_schema.columns WHERE table_schema = ? AND table_name = ? AND column_name = ?",
+        &params,
+    )?;
+
+    Ok(rows.is_some())
+}
+
+fn has_index(conn: &mut MSQLWrapper, db: &str, table: &str, index: &str) -> Result<bool, Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(db)?;
+    params.append_str(table)?;
+    params.append_str(index)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT index_name FROM information_schema.statistics WHERE table_schema = ? AND table_name = ? AND index_name = ?",
+        &params,
+    )?;
+
+    Ok(rows.is_some())
+}
+
+// Timestamps used to be stored in the db server's local time. They are
+// converted once using the offset specified by the operator.
+fn migrate_timestamps_to_utc(conn: &mut MSQLWrapper, config: &Config) -> Result<(), Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(META_TIMESTAMPS_UTC)?;
+
+    let rows = conn.query_with_params_rows("SELECT value FROM META WHERE name = ?", &params)?;
+    if rows.is_some() {
+        return Ok(());
+    }
+
+    let rows = conn.query_with_params_rows(
+        "SELECT uuid FROM COMMENT2 LIMIT 1",
+        &MSQLParamsWrapper::new(),
+    )?;
+
+    if rows.is_some() {
+        let offset = config.get_timestamp_migration_offset().ok_or(Error::from(
+            "Existing timestamps are not known to be UTC! Set \"timestamp_migration_offset\" in the config to the UTC offset they were stored with (\"+00:00\" if they are already UTC).",
+        ))?;
+
+        println!("Migrating timestamps from UTC offset {} to UTC...", offset);
+
+        params = MSQLParamsWrapper::new();
+        params.append_str(offset)?;
+        params.append_str(offset)?;
+        params.append_str(offset)?;
+
+        conn.query_with_params_drop(
+            r"UPDATE COMMENT2 SET
+                creation_date = CONVERT_TZ(creation_date, ?, '+00:00'),
+                edit_date = CONVERT_TZ(edit_date, ?, '+00:00'),
+                deleted_at = CONVERT_TZ(deleted_at, ?, '+00:00')",
+            &params,
+        )?;
+
+        params = MSQLParamsWrapper::new();
+        params.append_str(offset)?;
+        params.append_str(offset)?;
+
+        conn.query_with_params_drop(
+            r"UPDATE COMMENT_REVISION SET
+                revision_date = CONVERT_TZ(revision_date, ?, '+00:00'),
+                replaced_date = CONVERT_TZ(replaced_date, ?, '+00:00')",
+            &params,
+        )?;
+
+        // Logins and pending actions are temporary.
+        conn.query_drop("DELETE FROM LOGIN2")?;
+        conn.query_drop("DELETE FROM PENDING_ACTION")?;
+    }
+
+    params = MSQLParamsWrapper::new();
+    params.append_str(META_TIMESTAMPS_UTC)?;
+
+    conn.query_with_params_drop("INSERT INTO META (name, value) VALUES (?, '1')", &params)?;
+
+    Ok(())
+}
+
+// Registers the given blog ids if they are not already registered.
+pub fn import_blogs(sql_ctx: SQLCtx, blog_ids: &[String]) -> Result<u64, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let mut count: u64 = 0;
+
+    for blog_id in blog_ids {
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(blog_id)?;
+
+        tx.query_with_params_drop("INSERT IGNORE INTO BLOG (id) VALUES (?)", &params)?;
+
+        count += get_affected_rows(&mut tx)?;
+    }
+
+    tx.commit()?;
+
+    Ok(count)
+}
+
+// Registers a blog on its first comment, if it is not already registered.
+fn register_blog(conn: &mut MSQLWrapper, blog_id: &str, url: Option<&str>) -> Result<(), Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+    if let Some(url) = url {
+        params.append_str(url)?;
+    } else {
+        params.append_null();
+    }
+
+    conn.query_with_params_drop("INSERT IGNORE INTO BLOG (id, url) VALUES (?, ?)", &params)?;
+
+    Ok(())
+}
+
+const BLOG_COLUMNS: &str = "id, url, title, is_open, creation_date, post_date, author, (SELECT MIN(creation_date) FROM COMMENT2 WHERE COMMENT2.blog_post_id = BLOG.id)";
+
+fn row_to_blog(
+    row: &[msql_ffi::MSQLValueEnum],
+    auto_close_days: u64,
+    auto_close_from: AutoCloseFrom,
+) -> Result<Blog, Error> {
+    let BlogRow {
+        id: blog_id,
+        url,
+        title,
+        is_open: open,
+        creation_date: create_date,
+        post_date,
+        author,
+        first_comment_date,
+    } = BlogRow::from_row(row)?;
+
+    // Blogs without a post date are auto-closed from their first comment.
+    let auto_close_start = match auto_close_from {
+        AutoCloseFrom::FirstComment => first_comment_date,
+        AutoCloseFrom::PostDate => post_date.or(first_comment_date),
+    };
+    let auto_closed = auto_close_days != 0
+        && auto_close_start.is_some_and(|start| {
+            OffsetDateTime::now_utc() >= start + time::Duration::days(auto_close_days as i64)
+        });
+
+    Ok(Blog {
+        blog_id,
+        url,
+        title,
+        open,
+        closed: !open || auto_closed,
+        create_date: create_date.format(&Rfc3339)?,
+        create_date_ms: to_epoch_ms(create_date),
+        post_date: post_date.map(|d| d.format(&Rfc3339)).transpose()?,
+        post_date_ms: post_date.map(to_epoch_ms),
+        first_comment_date: first_comment_date.map(|d| d.format(&Rfc3339)).transpose()?,
+        first_comment_date_ms: first_comment_date.map(to_epoch_ms),
+        author,
+    })
+}
+
+pub fn get_blog(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    auto_close_days: u64,
+    auto_close_from: AutoCloseFrom,
+) -> Result<Option<Blog>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+
+    let rows = conn.query_with_params_rows(
+        &format!("SELECT {} FROM BLOG WHERE id = ?", BLOG_COLUMNS),
+        &params,
+    )?;
+
+    if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        Ok(Some(row_to_blog(
+            &rows[0],
+            auto_close_days,
+            auto_close_from,
+        )?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn get_blogs(
+    sql_ctx: SQLCtx,
+    auto_close_days: u64,
+    auto_close_from: AutoCloseFrom,
+) -> Result<Vec<Blog>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let rows = conn.query_with_params_rows(
+        &format!("SELECT {} FROM BLOG ORDER BY creation_date", BLOG_COLUMNS),
+        &MSQLParamsWrapper::new(),
+    )?;
+
+    let mut blogs: Vec<Blog> = Vec::new();
+
+    for row in rows.iter().flatten() {
+        blogs.push(row_to_blog(row, auto_close_days, auto_close_from)?);
+    }
+
+    Ok(blogs)
+}
+
+pub fn set_blog(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    url: Option<&str>,
+    title: Option<&str>,
+    open: bool,
+    post_date: Option<OffsetDateTime>,
+    author: Option<&str>,
+) -> Result<(), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+    if let Some(url) = url {
+        params.append_str(url)?;
+    } else {
+        params.append_null();
+    }
+    if let Some(title) = title {
+        params.append_str(title)?;
+    } else {
+        params.append_null();
+    }
+    params.append_uint64(if open { 1 } else { 0 });
+    if let Some(post_date) = post_date {
+        params.append_str(
+            &post_date
+                .to_offset(time::UtcOffset::UTC)
+                .format(DB_DATETIME_FORMAT)?,
+        )?;
+    } else {
+        params.append_null();
+    }
+    if let Some(author) = author {
+        params.append_str(author)?;
+    } else {
+        params.append_null();
+    }
+
+    conn.query_with_params_drop(
+        "INSERT INTO BLOG (id, url, title, is_open, post_date, author) VALUES (?, ?, ?, ?, ?, ?) ON DUPLICATE KEY UPDATE url = VALUES(url), title = VALUES(title), is_open = VALUES(is_open), post_date = VALUES(post_date), author = VALUES(author)",
+        &params,
+    )?;
+
+    Ok(())
+}
+
+pub fn remove_blog(sql_ctx: SQLCtx, blog_id: &str) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+
+    conn.query_with_params_drop("DELETE FROM BLOG WHERE id = ?", &params)?;
+
+    Ok(get_affected_rows(&mut conn)? == 1)
+}
+
+// "parent_id" is the comment being replied to by a "create" action.
+pub fn create_pending_action(
+    sql_ctx: SQLCtx,
+    action: PendingAction,
+    target: &str,
+    parent_id: Option<&str>,
+) -> Result<String, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    // "target" is the blog_id for a "create" action, the comment_id for
+    // "edit" and "delete" actions, and the url to return to for a "login"
+    // action.
+    // The state of a "create" action becomes the new comment's uuid, so it
+    // must not collide with an existing comment either.
+    let mut state = uuid::Uuid::new_v4().to_string();
+    loop {
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(&state)?;
+        params.append_str(&state)?;
+
+        let rows = tx.query_with_params_rows(
+            "SELECT state FROM PENDING_ACTION WHERE state = ? UNION SELECT uuid FROM COMMENT2 WHERE uuid = ?",
+            &params,
+        )?;
+
+        if rows.is_none() {
+            break;
+        }
+
+        state = uuid::Uuid::new_v4().to_string();
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(&state)?;
+    params.append_str(action.as_str())?;
+    params.append_str(target)?;
+    if let Some(parent_id) = parent_id {
+        params.append_str(parent_id)?;
+    } else {
+        params.append_null();
+    }
+    params.append_uint64(PENDING_ACTION_TIMEOUT_MINUTES);
+
+    tx.query_with_params_drop(
+        "INSERT INTO PENDING_ACTION (state, action, target, parent_id, expire_date) VALUES (?, ?, ?, ?, DATE_ADD(CURRENT_TIMESTAMP, INTERVAL ? MINUTE))",
+        &params,
+    )?;
+
+    tx.commit()?;
+
+    Ok(state)
+}
+
+pub fn check_pending_action(
+    sql_ctx: SQLCtx,
+    state: &str,
+    action: PendingAction,
+    target: &str,
+) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+    params.append_str(action.as_str())?;
+    params.append_str(target)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT state FROM PENDING_ACTION WHERE state = ? AND action = ? AND target = ? AND expire_date > CURRENT_TIMESTAMP",
+        &params,
+    )?;
+
+    Ok(rows.is_some())
+}
+
+pub fn get_pending_action_target(
+    sql_ctx: SQLCtx,
+    state: &str,
+    action: PendingAction,
+) -> Result<Option<String>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+    params.append_str(action.as_str())?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT target FROM PENDING_ACTION WHERE state = ? AND action = ? AND expire_date > CURRENT_TIMESTAMP",
+        &params,
+    )?;
+
+    if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        Ok(Some(get_column(&rows[0], 0, "PENDING_ACTION", "target")?))
+    } else {
+        Ok(None)
+    }
+}
+
+pub fn bind_pending_action_user(
+    sql_ctx: SQLCtx,
+    state: &str,
+    action: PendingAction,
+    user_id: u64,
+    user_name: &str,
+    user_url: &str,
+    user_avatar_url: &str,
+) -> Result<(), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+    params.append_str(action.as_str())?;
+
+    let rows = tx.query_with_params_rows(
+        "SELECT state FROM PENDING_ACTION WHERE state = ? AND action = ? AND expire_date > CURRENT_TIMESTAMP FOR UPDATE",
+        &params,
+    )?;
+
+    if rows.is_none() {
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Timed out binding user to pending action!",
+        ));
+    }
+
+    params = MSQLParamsWrapper::new();
+    params.append_uint64(user_id);
+    params.append_str(user_name)?;
+    params.append_str(user_url)?;
+    params.append_str(user_avatar_url)?;
+    params.append_str(state)?;
+
+    tx.query_with_params_drop(
+        "UPDATE PENDING_ACTION SET user_id = ?, username = ?, userurl = ?, useravatar = ? WHERE state = ?",
+        &params,
+    )?;
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn remove_pending_action(sql_ctx: SQLCtx, state: &str) -> Result<(), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    delete_pending_action(&mut conn, state)
+}
+
+fn delete_pending_action(conn: &mut MSQLWrapper, state: &str) -> Result<(), Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+
+    conn.query_with_params_drop("DELETE FROM PENDING_ACTION WHERE state = ?", &params)?;
+
+    Ok(())
+}
+
+pub fn add_comment(
+    sql_ctx: SQLCtx,
+    state: &str,
+    comment: &str,
+    blog_url_template: Option<&str>,
+) -> Result<PseudoComment, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+    params.append_str(PendingAction::Create.as_str())?;
+
+    let rows = tx.query_with_params_rows(
+        "SELECT user_id, username, userurl, useravatar, target, parent_id FROM PENDING_ACTION WHERE state = ? AND action = ? AND user_id IS NOT NULL AND expire_date > CURRENT_TIMESTAMP FOR UPDATE",
+        &params,
+    )?;
+
+    let pending: PendingCreateRow = if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        PendingCreateRow::from_row(&rows[0])?
+    } else {
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Timed out creating comment!",
+        ));
+    };
+
+    let pseudo_comment = PseudoComment {
+        user_id: pending.user_id,
+        username: pending.username,
+        userurl: pending.userurl,
+        useravatar: pending.useravatar,
+        blog_post_id: pending.target,
+        comment_id: state.to_owned(),
+        parent_id: pending.parent_id,
+    };
+
+    insert_comment(&mut tx, &pseudo_comment, comment, blog_url_template)?;
+
+    params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+
+    tx.query_with_params_drop("DELETE FROM PENDING_ACTION WHERE state = ?", &params)?;
+
+    tx.commit()?;
+
+    Ok(pseudo_comment)
+}
+
+// Adds a comment for a logged in user without a pending action.
+pub fn add_comment_as_user(
+    sql_ctx: SQLCtx,
+    login: &LoginInfo,
+    blog_id: &str,
+    parent_id: Option<&str>,
+    comment: &str,
+    blog_url_template: Option<&str>,
+) -> Result<PseudoComment, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    // Pending "create" states become comment uuids too, see
+    // create_pending_action().
+    let mut comment_id = uuid::Uuid::new_v4().to_string();
+    loop {
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(&comment_id)?;
+        params.append_str(&comment_id)?;
+
+        let rows = tx.query_with_params_rows(
+            "SELECT state FROM PENDING_ACTION WHERE state = ? UNION SELECT uuid FROM COMMENT2 WHERE uuid = ?",
+            &params,
+        )?;
+
+        if rows.is_none() {
+            break;
+        }
+
+        comment_id = uuid::Uuid::new_v4().to_string();
+    }
+
+    let pseudo_comment = PseudoComment {
+        user_id: login.user_github_id,
+        username: login.username.clone(),
+        userurl: login.userurl.clone(),
+        useravatar: login.useravatar.clone(),
+        blog_post_id: blog_id.to_owned(),
+        comment_id,
+        parent_id: parent_id.map(str::to_owned),
+    };
+
+    insert_comment(&mut tx, &pseudo_comment, comment, blog_url_template)?;
+
+    tx.commit()?;
+
+    Ok(pseudo_comment)
+}
+
+fn insert_comment(
+    conn: &mut MSQLWrapper,
+    pseudo_comment: &PseudoComment,
+    comment: &str,
+    blog_url_template: Option<&str>,
+) -> Result<(), Error> {
+    // The parent was checked before, but may have been deleted since. Locking
+    // it keeps it from being deleted until this comment is committed.
+    if let Some(parent_id) = &pseudo_comment.parent_id {
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(parent_id)?;
+        params.append_str(&pseudo_comment.blog_post_id)?;
+
+        let rows = conn.query_with_params_rows(
+            "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND blog_post_id = ? AND deleted_at IS NULL FOR UPDATE",
+            &params,
+        )?;
+
+        if rows.is_none() {
+            return Err(Error::coded(
+                ErrorCode::InvalidReply,
+                "The comment being replied to does not exist.",
+            ));
+        }
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(&pseudo_comment.comment_id)?;
+    params.append_str(&pseudo_comment.blog_post_id)?;
+    params.append_uint64(pseudo_comment.user_id);
+    params.append_str(&pseudo_comment.username)?;
+    params.append_str(&pseudo_comment.userurl)?;
+    params.append_str(&pseudo_comment.useravatar)?;
+    params.append_str(comment)?;
+    if let Some(parent_id) = &pseudo_comment.parent_id {
+        params.append_str(parent_id)?;
+    } else {
+        params.append_null();
+    }
+
+    conn.query_with_params_drop(
+        "INSERT INTO COMMENT2 (uuid, blog_post_id, user_id, username, userurl, useravatar, comment, parent_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        &params,
+    )?;
+
+    bump_version(conn, &pseudo_comment.comment_id)?;
+
+    let blog_url: Option<String> = blog_url_template
+        .map(|template| crate::allowlist::canonical_url(template, &pseudo_comment.blog_post_id));
+    register_blog(conn, &pseudo_comment.blog_post_id, blog_url.as_deref())
+}
+
+pub fn check_edit_comment_auth(sql_ctx: SQLCtx, cid: &str, uid: &str) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+    params.append_str(uid)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND user_id = ? AND deleted_at IS NULL",
+        &params,
+    )?;
+
+    Ok(rows.is_some())
+}
+
+// Also returns when the comment was last modified.
+pub fn get_comment_text(sql_ctx: SQLCtx, cid: &str) -> Result<(String, OffsetDateTime), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT comment, modified_date from COMMENT2 WHERE uuid = ? AND deleted_at IS NULL",
+        &params,
+    )?;
+
+    if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        Ok((
+            get_column(&rows[0], 0, "COMMENT2", "comment")?,
+            get_column(&rows[0], 1, "COMMENT2", "modified_date")?,
+        ))
+    } else {
+        Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist!",
+        ))
+    }
+}
+
+pub fn edit_comment(
+    sql_ctx: SQLCtx,
+    state: &str,
+    comment_id: &str,
+    comment: &str,
+) -> Result<(), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+    params.append_str(PendingAction::Edit.as_str())?;
+    params.append_str(comment_id)?;
+
+    let rows = tx.query_with_params_rows(
+        "SELECT user_id FROM PENDING_ACTION WHERE state = ? AND action = ? AND target = ? AND user_id IS NOT NULL AND expire_date > CURRENT_TIMESTAMP FOR UPDATE",
+        &params,
+    )?;
+
+    let user_id: u64 = if let Some(rows) = rows
+        && rows.len() == 1
+    {
+        get_column(&rows[0], 0, "PENDING_ACTION", "user_id")?
+    } else {
+        return Err(Error::coded(
+            ErrorCode::StateExpired,
+            "Timed out editing comment!",
+        ));
+    };
+
+    if !update_comment(&mut tx, comment_id, user_id, comment)? {
+        // Discard the revision inserted by update_comment().
+        tx.rollback()?;
+        return Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist or was deleted!",
+        ));
+    }
+
+    params = MSQLParamsWrapper::new();
+    params.append_str(state)?;
+
+    tx.query_with_params_drop("DELETE FROM PENDING_ACTION WHERE state = ?", &params)?;
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Returns false if the user has no such comment or it was deleted.
+pub fn edit_comment_by_user(
+    sql_ctx: SQLCtx,
+    comment_id: &str,
+    user_id: u64,
+    comment: &str,
+) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    if !update_comment(&mut tx, comment_id, user_id, comment)? {
+        tx.rollback()?;
+        return Ok(false);
+    }
+
+    tx.commit()?;
+
+    Ok(true)
+}
+
+// Saves the current text as a revision before updating. The caller must roll
+// back if this returns false.
+fn update_comment(
+    conn: &mut MSQLWrapper,
+    comment_id: &str,
+    user_id: u64,
+    comment: &str,
+) -> Result<bool, Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(comment_id)?;
+    params.append_uint64(user_id);
+
+    conn.query_with_params_drop(
+        "INSERT INTO COMMENT_REVISION (comment_id, revision_date, comment) SELECT uuid, edit_date, comment FROM COMMENT2 WHERE uuid = ? AND user_id = ? AND deleted_at IS NULL",
+        &params,
+    )?;
+
+    params = MSQLParamsWrapper::new();
+    params.append_str(comment)?;
+    params.append_str(comment_id)?;
+    params.append_uint64(user_id);
+
+    conn.query_with_params_drop(
+        "UPDATE COMMENT2 SET edit_date = CURRENT_TIMESTAMP, comment = ? WHERE uuid = ? AND user_id = ? AND deleted_at IS NULL",
+        &params,
+    )?;
+
+    if get_affected_rows(conn)? != 1 {
+        return Ok(false);
+    }
+
+    bump_version(conn, comment_id)?;
+
+    Ok(true)
+}
+
+// Also removes the pending action of "state" if given, even if the comment
+// wasn't deleted.
+pub fn try_delete_comment(
+    sql_ctx: SQLCtx,
+    cid: &str,
+    uid: u64,
+    deleted_by: &str,
+    reason: Option<&str>,
+    state: Option<&str>,
+) -> Result<bool, Error> {
+    delete_comment(sql_ctx, cid, Some(uid), deleted_by, reason, state)
+}
+
+// Like try_delete_comment(), but for the comment of any user.
+pub fn try_delete_comment_id_only(
+    sql_ctx: SQLCtx,
+    cid: &str,
+    deleted_by: &str,
+    reason: Option<&str>,
+    state: Option<&str>,
+) -> Result<bool, Error> {
+    delete_comment(sql_ctx, cid, None, deleted_by, reason, state)
+}
+
+fn delete_comment(
+    sql_ctx: SQLCtx,
+    cid: &str,
+    uid: Option<u64>,
+    deleted_by: &str,
+    reason: Option<&str>,
+    state: Option<&str>,
+) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let user_condition: &str = if uid.is_some() {
+        " AND user_id = ?"
+    } else {
+        ""
+    };
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+    if let Some(uid) = uid {
+        params.append_uint64(uid);
+    }
+
+    let rows = tx.query_with_params_rows(
+        &format!(
+            "SELECT uuid FROM COMMENT2 WHERE uuid = ?{} AND deleted_at IS NULL FOR UPDATE",
+            user_condition
+        ),
+        &params,
+    )?;
+
+    let deleted: bool = rows.is_some();
+    if deleted {
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(deleted_by)?;
+        if let Some(reason) = reason {
+            params.append_str(reason)?;
+        } else {
+            params.append_null();
+        }
+        params.append_str(cid)?;
+
+        tx.query_with_params_drop(
+            "UPDATE COMMENT2 SET deleted_at = CURRENT_TIMESTAMP, deleted_by = ?, delete_reason = ?, pinned = FALSE WHERE uuid = ?",
+            &params,
+        )?;
+
+        bump_version(&mut tx, cid)?;
+    }
+
+    if let Some(state) = state {
+        delete_pending_action(&mut tx, state)?;
+    }
+
+    tx.commit()?;
+
+    Ok(deleted)
+}
+
+pub fn restore_comment(sql_ctx: SQLCtx, cid: &str) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+
+    let rows = tx.query_with_params_rows(
+        "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND deleted_at IS NOT NULL FOR UPDATE",
+        &params,
+    )?;
+    if rows.is_none() {
+        return Ok(false);
+    }
+
+    tx.query_with_params_drop(
+        "UPDATE COMMENT2 SET deleted_at = NULL, deleted_by = NULL, delete_reason = NULL WHERE uuid = ?",
+        &params,
+    )?;
+
+    bump_version(&mut tx, cid)?;
+
+    tx.commit()?;
+
+    Ok(true)
+}
+
+pub fn purge_deleted_comments(sql_ctx: SQLCtx, retention_days: u64) -> Result<u64, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    let purged: &str = "deleted_at IS NOT NULL AND TIMESTAMPDIFF(DAY, deleted_at, CURRENT_TIMESTAMP) >= ? AND uuid NOT IN (SELECT parent_id FROM (SELECT parent_id FROM COMMENT2 WHERE parent_id IS NOT NULL) AS REPLY)";
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_uint64(retention_days);
+
+    tx.query_with_params_drop(
+        &format!(
+            "INSERT INTO BLOG_VERSION (blog_post_id, version) SELECT DISTINCT blog_post_id, 1 FROM COMMENT2 WHERE {} ON DUPLICATE KEY UPDATE version = version + 1",
+            purged
+        ),
+        &params,
+    )?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_uint64(retention_days);
+
+    tx.query_with_params_drop(&format!("DELETE FROM COMMENT2 WHERE {}", purged), &params)?;
+
+    let count = get_affected_rows(&mut tx)?;
+
+    if count != 0 {
+        tx.query_drop(
+            "DELETE FROM COMMENT_REVISION WHERE comment_id NOT IN (SELECT uuid FROM COMMENT2)",
+        )?;
+        tx.query_drop("DELETE FROM REACTION WHERE comment_id NOT IN (SELECT uuid FROM COMMENT2)")?;
+    }
+
+    tx.commit()?;
+
+    Ok(count)
+}
+
+pub fn get_deleted_comments(
+    sql_ctx: SQLCtx,
+    blog_id: Option<&str>,
+) -> Result<Vec<DeletedComment>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    let rows = if let Some(blog_id) = blog_id {
+        params.append_str(blog_id)?;
+        conn.query_with_params_rows("SELECT uuid, blog_post_id, username, userurl, creation_date, deleted_at, deleted_by, delete_reason, comment FROM COMMENT2 WHERE deleted_at IS NOT NULL AND blog_post_id = ? ORDER BY deleted_at DESC", &params)?
+    } else {
+        conn.query_with_params_rows("SELECT uuid, blog_post_id, username, userurl, creation_date, deleted_at, deleted_by, delete_reason, comment FROM COMMENT2 WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC", &params)?
+    };
+
+    let mut comments: Vec<DeletedComment> = Vec::new();
+
+    for row in rows.iter().flatten() {
+        let row = DeletedCommentRow::from_row(row)?;
+
+        comments.push(DeletedComment {
+            comment_id: row.uuid,
+            blog_id: row.blog_post_id.unwrap_or_default(),
+            username: row.username.unwrap_or_default(),
+            userurl: row.userurl.unwrap_or_default(),
+            create_date: row.creation_date.format(&Rfc3339)?,
+            deleted_date: row.deleted_at.format(&Rfc3339)?,
+            deleted_by: row.deleted_by.unwrap_or_default(),
+            delete_reason: row.delete_reason,
+            comment: row.comment.unwrap_or_default(),
+        });
+    }
+
+    Ok(comments)
+}
+
+// Unlike "get_comment", this is the same for deleted comments.
+pub fn get_comment_moderation(
+    sql_ctx: SQLCtx,
+    cid: &str,
+) -> Result<Option<CommentModeration>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT userurl, modified_date, deleted_at, deleted_by, delete_reason FROM COMMENT2 WHERE uuid = ?",
+        &params,
+    )?;
+
+    let Some(rows) = rows else {
+        return Ok(None);
+    };
+
+    let row = CommentModerationRow::from_row(&rows[0])?;
+
+    Ok(Some(CommentModeration {
+        userurl: row.userurl,
+        modified_date: row.modified_date,
+        deleted_date: row.deleted_at.map(|d| d.format(&Rfc3339)).transpose()?,
+        deleted_date_ms: row.deleted_at.map(to_epoch_ms),
+        deleted_by: row.deleted_by,
+        delete_reason: row.delete_reason,
+    }))
+}
+
+// Searches comments that aren't deleted. With text, the most relevant are
+// first, otherwise the newest. Also returns the number of matching comments.
+pub fn search_comments(
+    sql_ctx: SQLCtx,
+    filter: &SearchFilter,
+    limit: u64,
+    offset: u64,
+) -> Result<(Vec<SearchHit>, u64), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut conditions: Vec<&str> = vec!["deleted_at IS NULL"];
+    let mut values: Vec<String> = Vec::new();
+    if let Some(text) = filter.text {
+        conditions.push("MATCH (comment) AGAINST (? IN NATURAL LANGUAGE MODE)");
+        values.push(text.to_owned());
+    }
+    if let Some(author) = filter.author {
+        conditions.push("(username = ? OR userurl = CONCAT('https://github.com/', ?))");
+        values.push(author.to_owned());
+        values.push(author.to_owned());
+    }
+    if let Some(blog_id) = filter.blog_id {
+        conditions.push("blog_post_id = ?");
+        values.push(blog_id.to_owned());
+    }
+    if let Some(from) = filter.from {
+        conditions.push("creation_date >= ?");
+        values.push(
+            from.to_offset(time::UtcOffset::UTC)
+                .format(DB_DATETIME_FORMAT)?,
+        );
+    }
+    if let Some(to) = filter.to {
+        conditions.push("creation_date <= ?");
+        values.push(
+            to.to_offset(time::UtcOffset::UTC)
+                .format(DB_DATETIME_FORMAT)?,
+        );
+    }
+    let conditions: String = conditions.join(" AND ");
+
+    let mut params = MSQLParamsWrapper::new();
+    for value in &values {
+        params.append_str(value)?;
+    }
+
+    let rows = conn.query_with_params_rows(
+        &format!("SELECT COUNT(*) FROM COMMENT2 WHERE {}", conditions),
+        &params,
+    )?;
+    let total: u64 = match rows {
+        Some(rows) => get_column(&rows[0], 0, "COMMENT2", "count")?,
+        None => 0,
+    };
+
+    let order: &str = if let Some(text) = filter.text {
+        params.append_str(text)?;
+        "MATCH (comment) AGAINST (? IN NATURAL LANGUAGE MODE) DESC, creation_date DESC, uuid"
+    } else {
+        "creation_date DESC, uuid"
+    };
+    params.append_uint64(limit);
+    params.append_uint64(offset);
+
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT uuid, blog_post_id, username, userurl, useravatar, creation_date, comment FROM COMMENT2 WHERE {} ORDER BY {} LIMIT ? OFFSET ?",
+            conditions, order
+        ),
+        &params,
+    )?;
+
+    let mut hits: Vec<SearchHit> = Vec::new();
+    for row in rows.iter().flatten() {
+        match SearchHitRow::from_row(row) {
+            Ok(row) => hits.push(SearchHit {
+                comment_id: row.uuid,
+                blog_id: row.blog_post_id,
+                username: row.username,
+                userurl: row.userurl,
+                useravatar: row.useravatar,
+                create_date: row.creation_date.format(&Rfc3339)?,
+                create_date_ms: to_epoch_ms(row.creation_date),
+                comment: row.comment,
+            }),
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    Ok((hits, total))
+}
+
+const COMMENT_COLUMNS: &str = "uuid, username, userurl, useravatar, creation_date, edit_date, comment, deleted_at, (SELECT COUNT(*) FROM COMMENT_REVISION WHERE COMMENT_REVISION.comment_id = COMMENT2.uuid), parent_id, pinned";
+
+// The depth is left at 0, it is set when arranging threads.
+fn row_to_comment(row: CommentRow) -> Result<Comment, Error> {
+    let CommentRow {
+        uuid: comment_id,
+        username,
+        userurl,
+        useravatar,
+        creation_date: create_date,
+        edit_date,
+        comment,
+        deleted_at,
+        edit_count,
+        parent_id,
+        pinned,
+    } = row;
+    let deleted: bool = deleted_at.is_some();
+
+    if deleted {
+        // Keep a placeholder so that the comment's position is kept.
+        return Ok(Comment {
+            comment_id,
+            username: DELETED_PLACEHOLDER.to_owned(),
+            userurl: String::new(),
+            useravatar: String::new(),
+            create_date: create_date.format(&Rfc3339)?,
+            create_date_ms: to_epoch_ms(create_date),
+            edit_date: edit_date.format(&Rfc3339)?,
+            edit_date_ms: to_epoch_ms(edit_date),
+            comment: DELETED_PLACEHOLDER.to_owned(),
+            comment_html: DELETED_PLACEHOLDER.to_owned(),
+            deleted,
+            edit_count,
+            parent_id,
+            depth: 0,
+            reactions: Vec::new(),
+            pinned,
+            admin: false,
+            moderator: false,
+            author: false,
+        });
+    }
+
+    Ok(Comment {
+        comment_id,
+        username,
+        userurl,
+        useravatar,
+        create_date: create_date.format(&Rfc3339)?,
+        create_date_ms: to_epoch_ms(create_date),
+        edit_date: edit_date.format(&Rfc3339)?,
+        edit_date_ms: to_epoch_ms(edit_date),
+        comment,
+        // Set by markdown::Renderer.
+        comment_html: String::new(),
+        deleted,
+        edit_count,
+        parent_id,
+        depth: 0,
+        reactions: Vec::new(),
+        pinned,
+        // Set from the config.
+        admin: false,
+        moderator: false,
+        author: false,
+    })
+}
+
+// Comments that aren't deleted get a reaction for each of "emojis", with the
+// counts of every user.
+pub fn get_comments_per_blog_id(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    hide_deleted: bool,
+    emojis: &[String],
+) -> Result<Vec<Comment>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+
+    // Deleted comments are always fetched so that threads with deleted
+    // parents stay intact.
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT {} FROM COMMENT2 WHERE blog_post_id = ? ORDER BY creation_date, uuid",
+            COMMENT_COLUMNS
+        ),
+        &params,
+    )?;
+
+    if rows.is_none() {
+        // No comments.
+        return Ok(Vec::new());
+    }
+
+    let mut comments: Vec<Comment> = Vec::new();
+
+    for row in rows.as_ref().unwrap() {
+        let row = match CommentRow::from_row(row) {
+            Ok(row) => row,
+            Err(e) => {
+                report_bad_row(&e);
+                continue;
+            }
+        };
+        comments.push(row_to_comment(row)?);
+    }
+
+    set_reactions(&mut conn, &mut comments, emojis)?;
+
+    Ok(thread::arrange(comments, hide_deleted))
+}
+
+#[derive(Clone)]
+enum KeyValue {
+    Uint(u64),
+    Str(String),
+}
+
+// Returns up to "limit" threads after "after" in "order", arranged as by
+// thread::arrange(), with reactions as in get_comments_per_blog_id(). Also
+// returns the position of the page's last thread if more threads follow, and
+// the number of threads. With "hide_deleted", deleted top-level comments are
+// only counted if they have replies.
+pub fn get_comment_page(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    hide_deleted: bool,
+    emojis: &[String],
+    order: Order,
+    after: Option<&Position>,
+    limit: usize,
+) -> Result<(Vec<Comment>, Option<Position>, u64), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let top_level: &str = if hide_deleted {
+        "blog_post_id = ? AND parent_id IS NULL AND (deleted_at IS NULL OR EXISTS (SELECT 1 FROM COMMENT2 AS REPLY WHERE REPLY.parent_id = COMMENT2.uuid))"
+    } else {
+        "blog_post_id = ? AND parent_id IS NULL"
+    };
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+
+    let rows = conn.query_with_params_rows(
+        &format!("SELECT COUNT(*) FROM COMMENT2 WHERE {}", top_level),
+        &params,
+    )?;
+    let total: u64 = match rows {
+        Some(rows) => get_column(&rows[0], 0, "COMMENT2", "count")?,
+        None => 0,
+    };
+
+    // Matches the sum of the comment's reactions from set_reactions().
+    let reactions: String = if emojis.is_empty() {
+        "0".to_owned()
+    } else {
+        format!(
+            "IF(deleted_at IS NULL, (SELECT COUNT(*) FROM REACTION WHERE REACTION.comment_id = COMMENT2.uuid AND REACTION.emoji IN ({})), 0)",
+            vec!["?"; emojis.len()].join(", ")
+        )
+    };
+
+    // The sort keys, with whether they are descending.
+    let date_desc: bool = order == Order::Newest;
+    let mut keys: Vec<(&str, bool)> = vec![("pinned", true)];
+    if order == Order::Top {
+        keys.push(("reactions", true));
+    }
+    keys.push(("creation_date", date_desc));
+    keys.push(("uuid", date_desc));
+
+    // Rows after "after" have a key that sorts after it, with every earlier
+    // key equal.
+    let mut keyset: String = String::new();
+    let mut values: Vec<KeyValue> = Vec::new();
+    if let Some(after) = after {
+        let create_date: OffsetDateTime =
+            OffsetDateTime::from_unix_timestamp_nanos(i128::from(after.create_date_ms) * 1_000_000)
+                .map_err(|_| Error::coded(ErrorCode::BadRequest, "Invalid cursor!"))?;
+        let mut after_values: Vec<KeyValue> = vec![KeyValue::Uint(u64::from(after.pinned))];
+        if order == Order::Top {
+            after_values.push(KeyValue::Uint(after.reactions));
+        }
+        after_values.push(KeyValue::Str(create_date.format(DB_DATETIME_FORMAT)?));
+        after_values.push(KeyValue::Str(after.comment_id.clone()));
+
+        for ((key, desc), value) in keys.iter().zip(after_values).rev() {
+            let op: &str = if *desc { "<" } else { ">" };
+            if keyset.is_empty() {
+                keyset = format!("{} {} ?", key, op);
+                values.push(value);
+            } else {
+                keyset = format!("{0} {1} ? OR ({0} = ? AND ({2}))", key, op, keyset);
+                values.splice(0..0, [value.clone(), value]);
+            }
+        }
+        keyset = format!(" WHERE {}", keyset);
+    }
+
+    let order_by: String = keys
+        .iter()
+        .map(|(key, desc)| format!("{}{}", key, if *desc { " DESC" } else { "" }))
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let mut params = MSQLParamsWrapper::new();
+    for emoji in emojis {
+        params.append_str(emoji)?;
+    }
+    params.append_str(blog_id)?;
+    for value in &values {
+        match value {
+            KeyValue::Uint(val) => params.append_uint64(*val),
+            KeyValue::Str(val) => params.append_str(val)?,
+        }
+    }
+    // One more than the page, to know if another page follows.
+    params.append_uint64(limit as u64 + 1);
+
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT uuid, creation_date, reactions, pinned FROM (SELECT uuid, creation_date, pinned, {} AS reactions FROM COMMENT2 WHERE {}) AS THREAD{} ORDER BY {} LIMIT ?",
+            reactions, top_level, keyset, order_by
+        ),
+        &params,
+    )?;
+
+    let mut positions: Vec<Position> = Vec::new();
+    for row in rows.iter().flatten() {
+        match ThreadPositionRow::from_row(row) {
+            Ok(row) => positions.push(Position {
+                create_date_ms: to_epoch_ms(row.creation_date),
+                comment_id: row.uuid,
+                reactions: row.reactions,
+                pinned: row.pinned,
+            }),
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    let last: Option<Position> = if positions.len() > limit {
+        positions.truncate(limit);
+        positions.last().cloned()
+    } else {
+        None
+    };
+
+    if positions.is_empty() {
+        return Ok((Vec::new(), last, total));
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    for position in &positions {
+        params.append_str(&position.comment_id)?;
+    }
+
+    // Deleted replies are fetched too, like in get_comments_per_blog_id().
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "WITH RECURSIVE THREAD (uuid) AS (SELECT uuid FROM COMMENT2 WHERE uuid IN ({}) UNION SELECT REPLY.uuid FROM COMMENT2 AS REPLY JOIN THREAD ON REPLY.parent_id = THREAD.uuid) SELECT {} FROM COMMENT2 WHERE uuid IN (SELECT uuid FROM THREAD) ORDER BY creation_date, uuid",
+            vec!["?"; positions.len()].join(", "),
+            COMMENT_COLUMNS
+        ),
+        &params,
+    )?;
+
+    let mut comments: Vec<Comment> = Vec::new();
+    for row in rows.iter().flatten() {
+        match CommentRow::from_row(row) {
+            Ok(row) => comments.push(row_to_comment(row)?),
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    set_reactions(&mut conn, &mut comments, emojis)?;
+
+    // Puts the arranged threads in the page's order.
+    let ranks: HashMap<&str, usize> = positions
+        .iter()
+        .enumerate()
+        .map(|(idx, position)| (position.comment_id.as_str(), idx))
+        .collect();
+    let mut threads: Vec<Vec<Comment>> = vec![Vec::new(); positions.len()];
+    let mut rank: Option<usize> = None;
+    for comment in thread::arrange(comments, hide_deleted) {
+        if comment.depth == 0 {
+            rank = ranks.get(comment.comment_id.as_str()).copied();
+        }
+        if let Some(rank) = rank {
+            threads[rank].push(comment);
+        }
+    }
+
+    Ok((threads.into_iter().flatten().collect(), last, total))
+}
+
+fn set_reactions(
+    conn: &mut MSQLWrapper,
+    comments: &mut [Comment],
+    emojis: &[String],
+) -> Result<(), Error> {
+    if comments.is_empty() || emojis.is_empty() {
+        return Ok(());
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    for comment in comments.iter() {
+        params.append_str(&comment.comment_id)?;
+    }
+
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT comment_id, emoji, COUNT(*) FROM REACTION WHERE comment_id IN ({}) GROUP BY comment_id, emoji",
+            vec!["?"; comments.len()].join(", ")
+        ),
+        &params,
+    )?;
+
+    let mut counts: HashMap<(String, String), u64> = HashMap::new();
+    for row in rows.iter().flatten() {
+        match ReactionCountRow::from_row(row) {
+            Ok(row) => {
+                counts.insert((row.comment_id, row.emoji), row.count);
+            }
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    for comment in comments.iter_mut().filter(|comment| !comment.deleted) {
+        comment.reactions = emojis
+            .iter()
+            .map(|emoji| Reaction {
+                emoji: emoji.to_owned(),
+                count: counts
+                    .get(&(comment.comment_id.clone(), emoji.to_owned()))
+                    .copied()
+                    .unwrap_or(0),
+                reacted: false,
+            })
+            .collect();
+    }
+
+    Ok(())
+}
+
+// Returns the comment ids and emojis of the user's reactions on the blog.
+pub fn get_user_reactions(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    user_id: u64,
+) -> Result<HashSet<(String, String)>, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+    params.append_uint64(user_id);
+
+    let rows = conn.query_with_params_rows(
+        "SELECT REACTION.comment_id, REACTION.emoji FROM REACTION JOIN COMMENT2 ON COMMENT2.uuid = REACTION.comment_id WHERE COMMENT2.blog_post_id = ? AND REACTION.user_id = ?",
+        &params,
+    )?;
+
+    let mut reactions: HashSet<(String, String)> = HashSet::new();
+    for row in rows.iter().flatten() {
+        match UserReactionRow::from_row(row) {
+            Ok(row) => {
+                reactions.insert((row.comment_id, row.emoji));
+            }
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    Ok(reactions)
+}
+
+// Does nothing if the user already reacted with the emoji. Returns false if
+// the comment doesn't exist or is deleted.
+pub fn add_reaction(sql_ctx: SQLCtx, cid: &str, user_id: u64, emoji: &str) -> Result<bool, Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    if !lock_live_comment(&mut tx, cid)? {
+        return Ok(false);
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+    params.append_uint64(user_id);
+    params.append_str(emoji)?;
+
+    tx.query_with_params_drop(
+        "INSERT IGNORE INTO REACTION (comment_id, user_id, emoji) VALUES (?, ?, ?)",
+        &params,
+    )?;
+
+    if get_affected_rows(&mut tx)? != 0 {
+        touch_comment(&mut tx, cid)?;
+    }
+
+    tx.commit()?;
+
+    Ok(true)
+}
+
+// Does nothing if the user hasn't reacted with the emoji, or if the comment
+// doesn't exist or is deleted.
+pub fn remove_reaction(sql_ctx: SQLCtx, cid: &str, user_id: u64, emoji: &str) -> Result<(), Error> {
+    let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
+    let mut conn = conn
+        .try_lock()
+        .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+
+    let mut tx = Transaction::begin(&mut conn)?;
+
+    if !lock_live_comment(&mut tx, cid)? {
+        return Ok(());
+    }
+
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+    params.append_uint64(user_id);
+    params.append_str(emoji)?;
+
+    tx.query_with_params_drop(
+        "DELETE FROM REACTION WHERE comment_id = ? AND user_id = ? AND emoji = ?",
+        &params,
+    )?;
+
+    if get_affected_rows(&mut tx)? != 0 {
+        touch_comment(&mut tx, cid)?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Locks the comment until the end of the transaction. Returns false if it
+// doesn't exist or is deleted.
+fn lock_live_comment(conn: &mut MSQLWrapper, cid: &str) -> Result<bool, Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+
+    let rows = conn.query_with_params_rows(
+        "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND deleted_at IS NULL FOR UPDATE",
+        &params,
+    )?;
 
-    Ok(())
+    Ok(rows.is_some())
 }
 
-pub fn has_psuedo_commment_with_state(sql_ctx: SQLCtx, state: &str) -> Result<bool, Error> {
+// Only top-level comments that aren't deleted can be pinned. Returns false if
+// the comment isn't one.
+pub fn set_comment_pinned(sql_ctx: SQLCtx, cid: &str, pinned: bool) -> Result<bool, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
+    let mut tx = Transaction::begin(&mut conn)?;
+
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(state)?;
+    params.append_str(cid)?;
 
-    let rows = conn
-        .query_with_params_rows("SELECT uuid FROM COMMENT2 WHERE uuid = ?", &params)
-        .map_err(|e| Error::Generic(e.to_owned()))?;
+    let rows = tx.query_with_params_rows(
+        "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND parent_id IS NULL AND deleted_at IS NULL FOR UPDATE",
+        &params,
+    )?;
+    if rows.is_none() {
+        return Ok(false);
+    }
 
-    Ok(rows.is_some())
-}
+    let mut params = MSQLParamsWrapper::new();
+    params.append_uint64(if pinned { 1 } else { 0 });
+    params.append_str(cid)?;
 
-pub fn create_rng_uuid(sql_ctx: SQLCtx, uuid: Option<&str>) -> Result<String, Error> {
-    // Ensure the sql_ctx has a connection.
-    let sql_ctx: SQLCtx = TryInto::<Arc<Mutex<MSQLWrapper>>>::try_into(sql_ctx)?.into();
+    tx.query_with_params_drop("UPDATE COMMENT2 SET pinned = ? WHERE uuid = ?", &params)?;
 
-    {
-        let sql_ctx_clone = sql_ctx.clone();
-        let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx_clone.try_into()?;
-        let mut conn = conn
-            .try_lock()
-            .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
+    bump_version(&mut tx, cid)?;
 
-        conn.query_drop(
-            r"DELETE FROM COMMENT2 WHERE timeout_date IS NOT NULL AND TIMESTAMPDIFF(MINUTE, timeout_date, CURRENT_TIMESTAMP) > 60"
-        )?;
-    }
+    tx.commit()?;
 
-    let mut rng_uuid = uuid::Uuid::new_v4();
+    Ok(true)
+}
 
-    loop {
-        let sql_ctx_clone = sql_ctx.clone();
+// Changes the comment's modified_date, so that caches see the change.
+fn touch_comment(conn: &mut MSQLWrapper, cid: &str) -> Result<(), Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
 
-        let ret: bool = has_psuedo_commment_with_state(sql_ctx_clone, &rng_uuid.to_string())?;
+    conn.query_with_params_drop(
+        "UPDATE COMMENT2 SET modified_date = CURRENT_TIMESTAMP WHERE uuid = ?",
+        &params,
+    )?;
 
-        if !ret {
-            break;
-        }
+    bump_version(conn, cid)
+}
 
-        rng_uuid = uuid::Uuid::new_v4();
-    }
+// Bumps the version of the comment's blog. Must be called after the change,
+// so that a body read before it is never cached under the new version.
+// modified_date can't be used for this, as it only has whole seconds.
+fn bump_version(conn: &mut MSQLWrapper, cid: &str) -> Result<(), Error> {
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
+
+    conn.query_with_params_drop(
+        "INSERT INTO BLOG_VERSION (blog_post_id, version) SELECT blog_post_id, 1 FROM COMMENT2 WHERE uuid = ? ON DUPLICATE KEY UPDATE version = version + 1",
+        &params,
+    )?;
+
+    Ok(())
+}
 
+pub fn get_comments_version(sql_ctx: SQLCtx, blog_id: &str) -> Result<CommentsVersion, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    let rng_uuid_string = rng_uuid.to_string();
-
-    if let Some(uuid_str) = uuid {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(&rng_uuid_string)?;
-        params.append_str(uuid_str)?;
-
-        conn.query_with_params_drop(
-            "UPDATE COMMENT2 SET state = ? WHERE uuid = ? AND timeout_date IS NULL",
-            &params,
-        )?;
-
-        params = MSQLParamsWrapper::new();
-        params.append_str(uuid_str)?;
-
-        let rows = conn.query_with_params_rows(
-            "SELECT state FROM COMMENT2 WHERE state IS NOT NULL AND uuid = ?",
-            &params,
-        )?;
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(blog_id)?;
+    params.append_str(blog_id)?;
+    params.append_str(blog_id)?;
 
-        if rows.is_none() {
-            return Err("Failed to add state to existing comment!".into());
-        }
-    } else {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(&rng_uuid_string)?;
+    let rows = conn.query_with_params_rows(
+        "SELECT COALESCE((SELECT version FROM BLOG_VERSION WHERE blog_post_id = ?), 0), MAX(modified_date), (SELECT author FROM BLOG WHERE id = ?) FROM COMMENT2 WHERE blog_post_id = ?",
+        &params,
+    )?;
 
-        conn.query_with_params_drop("INSERT INTO COMMENT2 (uuid) VALUES (?)", &params)?;
-    }
+    let row = CommentsVersionRow::from_row(
+        rows.as_ref()
+            .and_then(|rows| rows.first())
+            .ok_or(Error::from("Failed to query comments version!"))?,
+    )?;
 
-    Ok(rng_uuid_string)
+    Ok(CommentsVersion {
+        version: row.version,
+        last_modified: row.last_modified,
+        author: row.author,
+    })
 }
 
-pub fn check_rng_uuid(sql_ctx: SQLCtx, uuid: &str, state: Option<&str>) -> Result<bool, Error> {
+// Returns the blog id and the comment, with its depth set. Deleted comments
+// are returned as placeholders.
+pub fn get_comment(
+    sql_ctx: SQLCtx,
+    cid: &str,
+    emojis: &[String],
+) -> Result<Option<(String, Comment)>, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    conn.query_drop(
-        r"DELETE FROM COMMENT2 WHERE timeout_date IS NOT NULL AND TIMESTAMPDIFF(MINUTE, timeout_date, CURRENT_TIMESTAMP) > 60"
-    )?;
-
-    if let Some(state) = state {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(uuid)?;
-        params.append_str(state)?;
+    let mut params = MSQLParamsWrapper::new();
+    params.append_str(cid)?;
 
-        let rows = conn.query_with_params_rows(
-            "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND state = ? AND timeout_date IS NULL",
-            &params,
-        )?;
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT blog_post_id, {} FROM COMMENT2 WHERE uuid = ?",
+            COMMENT_COLUMNS
+        ),
+        &params,
+    )?;
 
-        Ok(rows.is_some())
-    } else {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(uuid)?;
+    let Some(rows) = rows else {
+        return Ok(None);
+    };
 
-        let rows = conn.query_with_params_rows(
-            "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND timeout_date IS NOT NULL",
-            &params,
-        )?;
+    let blog_id: String = get_column(&rows[0], 0, "COMMENT2", "blog_post_id")?;
+    let mut comment: Comment = row_to_comment(CommentRow::from_row(&rows[0][1..])?)?;
+    comment.depth = count_ancestors(&mut conn, cid, comment.parent_id.clone())?;
+    set_reactions(&mut conn, std::slice::from_mut(&mut comment), emojis)?;
 
-        Ok(rows.is_some())
-    }
+    Ok(Some((blog_id, comment)))
 }
 
-#[allow(clippy::too_many_arguments)]
-pub fn add_pseudo_comment_data(
+// Counts the comments that aren't deleted. Every given blog id is in the
+// returned map, with a count of 0 if it has no comments.
+pub fn get_comment_counts(
     sql_ctx: SQLCtx,
-    state: &str,
-    user_id: u64,
-    user_name: &str,
-    user_url: &str,
-    user_avatar_url: &str,
-    blog_post_id: Option<&str>,
-    comment_id: Option<&str>,
-) -> Result<String, Error> {
+    blog_ids: &[String],
+) -> Result<BTreeMap<String, CommentCount>, Error> {
+    let mut counts: BTreeMap<String, CommentCount> = blog_ids
+        .iter()
+        .map(|blog_id| {
+            (
+                blog_id.to_owned(),
+                CommentCount {
+                    count: 0,
+                    latest_comment_date: None,
+                    latest_comment_date_ms: None,
+                },
+            )
+        })
+        .collect();
+
+    if blog_ids.is_empty() {
+        return Ok(counts);
+    }
+
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    conn.query_drop(
-        r"DELETE FROM COMMENT2 WHERE timeout_date IS NOT NULL AND TIMESTAMPDIFF(MINUTE, timeout_date, CURRENT_TIMESTAMP) > 60"
-    )?;
-
-    if let Some(blog_id) = blog_post_id {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(state)?;
-
-        let rows = conn.query_with_params_rows(
-            "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND timeout_date IS NOT NULL",
-            &params,
-        )?;
-
-        if rows.is_none() {
-            return Err("Timed out creating comment!".into());
-        }
-
-        params = MSQLParamsWrapper::new();
-        params.append_uint64(user_id);
-        params.append_str(user_name)?;
-        params.append_str(user_url)?;
-        params.append_str(user_avatar_url)?;
+    let mut params = MSQLParamsWrapper::new();
+    for blog_id in blog_ids {
         params.append_str(blog_id)?;
-        params.append_str(state)?;
-
-        conn.query_with_params_drop("UPDATE COMMENT2 SET user_id=?, username=?, userurl=?, useravatar=?, blog_post_id=? WHERE uuid = ?", &params)?;
-    } else if let Some(comment_id) = comment_id {
-        let mut params = MSQLParamsWrapper::new();
-        params.append_str(comment_id)?;
-        params.append_str(state)?;
+    }
 
-        let rows = conn.query_with_params_rows(
-            "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND state = ? AND timeout_date IS NULL",
-            &params,
-        )?;
+    let rows = conn.query_with_params_rows(
+        &format!(
+            "SELECT blog_post_id, COUNT(*), MAX(creation_date) FROM COMMENT2 WHERE deleted_at IS NULL AND blog_post_id IN ({}) GROUP BY blog_post_id",
+            vec!["?"; blog_ids.len()].join(", ")
+        ),
+        &params,
+    )?;
 
-        if rows.is_none() {
-            return Err("Timed out creating comment!".into());
+    for row in rows.iter().flatten() {
+        match CommentCountRow::from_row(row) {
+            Ok(row) => {
+                counts.insert(
+                    row.blog_post_id,
+                    CommentCount {
+                        count: row.count,
+                        latest_comment_date: Some(row.latest_comment_date.format(&Rfc3339)?),
+                        latest_comment_date_ms: Some(to_epoch_ms(row.latest_comment_date)),
+                    },
+                );
+            }
+            Err(e) => report_bad_row(&e),
         }
-
-        params = MSQLParamsWrapper::new();
-        params.append_uint64(user_id);
-        params.append_str(user_name)?;
-        params.append_str(user_url)?;
-        params.append_str(user_avatar_url)?;
-        params.append_str(comment_id)?;
-
-        conn.query_with_params_drop("UPDATE COMMENT2 SET user_id=?, username=?, userurl=?, useravatar=?, state=NULL WHERE uuid = ?", &params)?;
     }
 
-    Ok(state.to_string())
+    Ok(counts)
 }
 
-pub fn add_comment(sql_ctx: SQLCtx, state: &str, comment: &str) -> Result<PseudoComment, Error> {
+// Returns None if the comment does not exist, or if it is deleted and
+// "include_deleted" is false. The last revision is the current comment text.
+pub fn get_comment_revisions(
+    sql_ctx: SQLCtx,
+    cid: &str,
+    include_deleted: bool,
+) -> Result<Option<Vec<CommentRevision>>, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    conn.query_drop(
-        r"DELETE FROM COMMENT2 WHERE timeout_date IS NOT NULL AND TIMESTAMPDIFF(MINUTE, timeout_date, CURRENT_TIMESTAMP) > 60"
-    )?;
+    let mut tx = Transaction::begin(&mut conn)?;
 
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(state)?;
-
-    let rows = conn.query_with_params_rows("SELECT uuid FROM COMMENT2 WHERE uuid = ?", &params)?;
+    params.append_str(cid)?;
 
-    if rows.is_none() {
-        return Err("Timed out creating comment!".into());
-    }
+    let current_rows = if include_deleted {
+        tx.query_with_params_rows(
+            "SELECT edit_date, comment FROM COMMENT2 WHERE uuid = ?",
+            &params,
+        )?
+    } else {
+        tx.query_with_params_rows(
+            "SELECT edit_date, comment FROM COMMENT2 WHERE uuid = ? AND deleted_at IS NULL",
+            &params,
+        )?
+    };
 
-    params = MSQLParamsWrapper::new();
-    params.append_str(comment)?;
-    params.append_str(state)?;
+    let Some(current_rows) = current_rows else {
+        return Ok(None);
+    };
 
-    conn.query_with_params_drop(
-        "UPDATE COMMENT2 SET timeout_date=NULL, comment=? WHERE uuid = ?",
+    let rows = tx.query_with_params_rows(
+        "SELECT revision_date, comment FROM COMMENT_REVISION WHERE comment_id = ? ORDER BY id",
         &params,
     )?;
 
-    params = MSQLParamsWrapper::new();
-    params.append_str(state)?;
+    let mut revisions: Vec<CommentRevision> = Vec::new();
 
-    let rows = conn.query_with_params_rows(
-        "SELECT user_id, username, userurl, useravatar, blog_post_id FROM COMMENT2 WHERE uuid = ?",
-        &params,
-    )?;
-
-    let user_id: Option<u64>;
-    let username: Option<String>;
-    let userurl: Option<String>;
-    let useravatar: Option<String>;
-    let blog_post_id: Option<String>;
-
-    if let Some(rows) = rows {
-        if rows.len() == 1 && rows[0].len() == 5 {
-            user_id = match &rows[0][0] {
-                msql_ffi::MSQLValueEnum::Int64(i) => Some(*i as u64),
-                msql_ffi::MSQLValueEnum::UInt64(u) => Some(*u),
-                _ => None,
-            };
-
-            username = match &rows[0][1] {
-                msql_ffi::MSQLValueEnum::String(s) => Some(s.to_owned()),
-                _ => None,
-            };
-
-            userurl = match &rows[0][2] {
-                msql_ffi::MSQLValueEnum::String(s) => Some(s.to_owned()),
-                _ => None,
-            };
-
-            useravatar = match &rows[0][3] {
-                msql_ffi::MSQLValueEnum::String(s) => Some(s.to_owned()),
-                _ => None,
-            };
-
-            blog_post_id = match &rows[0][4] {
-                msql_ffi::MSQLValueEnum::String(s) => Some(s.to_owned()),
-                _ => None,
-            };
-        } else {
-            return Err("Add comment: Failed to query pseudo comment (invalid length)".into());
-        }
-    } else {
-        return Err("Add comment: Failed to query pseudo comment (does not exist)".into());
-    }
-
-    Ok(PseudoComment {
-        user_id: user_id.ok_or(Into::<Error>::into("Add comment: Failed to parse user_id"))?,
-        username: username.ok_or(Into::<Error>::into("Add comment: Failed to parse username"))?,
-        userurl: userurl.ok_or(Into::<Error>::into("Add comment: Failed to parse userurl"))?,
-        useravatar: useravatar.ok_or(Into::<Error>::into(
-            "Add comment: Failed to parse useravatar",
-        ))?,
-        blog_post_id: blog_post_id.ok_or(Into::<Error>::into(
-            "Add comment: Failed to parse blog_post_id",
-        ))?,
-        comment_id: String::new(),
-    })
+    for row in rows.iter().flatten().chain(current_rows.iter()) {
+        let row = RevisionRow::from_row(row)?;
+
+        revisions.push(CommentRevision {
+            revision: revisions.len() as u64 + 1,
+            date: row.date.format(&Rfc3339)?,
+            date_ms: to_epoch_ms(row.date),
+            comment: row.comment.unwrap_or_default(),
+        });
+    }
+
+    tx.commit()?;
+
+    Ok(Some(revisions))
 }
 
-pub fn check_edit_comment_auth(sql_ctx: SQLCtx, cid: &str, uid: &str) -> Result<bool, Error> {
+// Returns the depth of the comment being replied to (0 for a top-level
+// comment), or None if it doesn't exist in the blog or is deleted.
+pub fn get_reply_parent_depth(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    parent_id: &str,
+) -> Result<Option<u64>, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(cid)?;
-    params.append_str(uid)?;
+    params.append_str(parent_id)?;
+    params.append_str(blog_id)?;
 
     let rows = conn.query_with_params_rows(
-        "SELECT uuid FROM COMMENT2 WHERE uuid = ? AND user_id = ?",
+        "SELECT parent_id FROM COMMENT2 WHERE uuid = ? AND blog_post_id = ? AND deleted_at IS NULL",
         &params,
     )?;
 
-    Ok(rows.is_some())
+    let Some(rows) = rows else {
+        return Ok(None);
+    };
+
+    let grandparent_id: Option<String> = get_column(&rows[0], 0, "COMMENT2", "parent_id")?;
+
+    Ok(Some(count_ancestors(&mut conn, parent_id, grandparent_id)?))
+}
+
+// Follows the "parent_id" chain of the comment "cid" up to a top-level
+// comment.
+fn count_ancestors(
+    conn: &mut MSQLWrapper,
+    cid: &str,
+    parent_id: Option<String>,
+) -> Result<u64, Error> {
+    let mut depth: u64 = 0;
+    let mut next_id: Option<String> = parent_id;
+    let mut visited: Vec<String> = vec![cid.to_owned()];
+    while let Some(id) = next_id.take() {
+        if visited.contains(&id) {
+            return Err("Cycle in comment replies!".into());
+        }
+
+        let mut params = MSQLParamsWrapper::new();
+        params.append_str(&id)?;
+
+        let rows =
+            conn.query_with_params_rows("SELECT parent_id FROM COMMENT2 WHERE uuid = ?", &params)?;
+
+        // Replies to a purged comment are treated as top-level comments.
+        let Some(rows) = rows else {
+            break;
+        };
+
+        depth += 1;
+        next_id = get_column(&rows[0], 0, "COMMENT2", "parent_id")?;
+        visited.push(id);
+    }
+
+    Ok(depth)
 }
 
-pub fn get_comment_text(sql_ctx: SQLCtx, cid: &str) -> Result<String, Error> {
+pub fn get_blog_id_by_comment_id(sql_ctx: SQLCtx, cid: &str) -> Result<String, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
@@ -490,206 +2602,116 @@ pub fn get_comment_text(sql_ctx: SQLCtx, cid: &str) -> Result<String, Error> {
     params.append_str(cid)?;
 
     let rows =
-        conn.query_with_params_rows("SELECT comment from COMMENT2 WHERE uuid = ?", &params)?;
+        conn.query_with_params_rows("SELECT blog_post_id FROM COMMENT2 WHERE uuid = ?", &params)?;
 
     if let Some(rows) = rows
         && rows.len() == 1
-        && rows[0].len() == 1
     {
-        match &rows[0][0] {
-            msql_ffi::MSQLValueEnum::Error => Err("Internal error fetching comment".into()),
-            msql_ffi::MSQLValueEnum::Null => Err("Internal error fetching comment".into()),
-            msql_ffi::MSQLValueEnum::Int64(_) => Err("Internal error fetching comment".into()),
-            msql_ffi::MSQLValueEnum::UInt64(_) => Err("Internal error fetching comment".into()),
-            msql_ffi::MSQLValueEnum::String(s) => Ok(s.to_owned()),
-            msql_ffi::MSQLValueEnum::DoubleF64(_) => Err("Internal error fetching comment".into()),
-        }
+        get_column(&rows[0], 0, "COMMENT2", "blog_post_id")
     } else {
-        Err("Internal error querying comment".into())
+        Err(Error::coded(
+            ErrorCode::CommentNotFound,
+            "Comment does not exist!",
+        ))
     }
 }
 
-pub fn edit_comment(sql_ctx: SQLCtx, uuid: &str, comment: &str) -> Result<(), Error> {
+pub fn add_comment_event(
+    sql_ctx: SQLCtx,
+    blog_id: &str,
+    kind: &str,
+    comment_id: &str,
+) -> Result<(), Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(comment)?;
-    params.append_str(uuid)?;
+    params.append_str(blog_id)?;
+    params.append_str(kind)?;
+    params.append_str(comment_id)?;
 
     conn.query_with_params_drop(
-        "UPDATE COMMENT2 SET edit_date = CURRENT_TIMESTAMP, comment = ? WHERE uuid = ?",
+        "INSERT INTO COMMENT_EVENT (blog_post_id, kind, comment_id) VALUES (?, ?, ?)",
         &params,
     )?;
 
     Ok(())
 }
 
-pub fn try_delete_comment(sql_ctx: SQLCtx, cid: &str, uid: u64) -> Result<(), Error> {
+// Returns events in the order they were added.
+pub fn get_comment_events_after(sql_ctx: SQLCtx, id: u64) -> Result<Vec<CommentEvent>, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(cid)?;
-    params.append_uint64(uid);
+    params.append_uint64(id);
 
-    conn.query_with_params_drop(
-        "DELETE FROM COMMENT2 WHERE uuid = ? AND user_id = ?",
+    let rows = conn.query_with_params_rows(
+        "SELECT id, blog_post_id, kind, comment_id FROM COMMENT_EVENT WHERE id > ? ORDER BY id",
         &params,
     )?;
 
-    Ok(())
+    let mut events: Vec<CommentEvent> = Vec::new();
+    for row in rows.iter().flatten() {
+        match CommentEvent::from_row(row) {
+            Ok(event) => events.push(event),
+            Err(e) => report_bad_row(&e),
+        }
+    }
+
+    Ok(events)
 }
 
-pub fn try_delete_comment_id_only(sql_ctx: SQLCtx, cid: &str) -> Result<(), Error> {
+pub fn get_last_comment_event_id(sql_ctx: SQLCtx) -> Result<u64, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    let mut params = MSQLParamsWrapper::new();
-    params.append_str(cid)?;
-
-    conn.query_with_params_drop("DELETE FROM COMMENT2 WHERE uuid = ?", &params)?;
+    let rows = conn.query_with_params_rows(
+        "SELECT COALESCE(MAX(id), 0) FROM COMMENT_EVENT",
+        &MSQLParamsWrapper::new(),
+    )?;
 
-    Ok(())
+    match rows {
+        Some(rows) => get_column(&rows[0], 0, "COMMENT_EVENT", "id"),
+        None => Ok(0),
+    }
 }
 
-pub fn get_comments_per_blog_id(sql_ctx: SQLCtx, blog_id: &str) -> Result<Vec<Comment>, Error> {
+pub fn cleanup_comment_events(sql_ctx: SQLCtx, minutes_timeout: u64) -> Result<u64, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    let utc_offset = UtcOffset::current_local_offset()?;
-
-    let parsing_format =
-        format_description::parse("[year]-[month]-[day]T[hour]:[minute]:[second]")?;
-
-    let output_format = format_description::parse(
-        "[year]-[month]-[day]T[hour]:[minute]:[second][offset_hour sign:mandatory]:[offset_minute]",
-    )?;
-
     let mut params = MSQLParamsWrapper::new();
-    params.append_str(blog_id)?;
-
-    let rows = conn.query_with_params_rows("SELECT uuid, username, userurl, useravatar, creation_date, edit_date, comment FROM COMMENT2 WHERE blog_post_id = ? ORDER BY creation_date", &params)?;
-
-    if rows.is_none() {
-        // No comments.
-        return Ok(Vec::new());
-    }
-
-    let mut comments: Vec<Comment> = Vec::new();
-
-    for row in rows.as_ref().unwrap() {
-        let comment_id: String = match &row[0] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => continue,
-        };
-        let username: String = match &row[1] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => continue,
-        };
-        let userurl: String = match &row[2] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => continue,
-        };
-        let useravatar: String = match &row[3] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => continue,
-        };
-        let create_date: PrimitiveDateTime = match &row[4] {
-            msql_ffi::MSQLValueEnum::String(s) => {
-                let res = PrimitiveDateTime::parse(s, &parsing_format);
-
-                if let Ok(ret_time) = res {
-                    ret_time
-                } else {
-                    continue;
-                }
-            }
-            _ => continue,
-        };
-        let edit_date: PrimitiveDateTime = match &row[5] {
-            msql_ffi::MSQLValueEnum::String(s) => {
-                let res = PrimitiveDateTime::parse(s, &parsing_format);
-
-                if let Ok(ret_time) = res {
-                    ret_time
-                } else {
-                    continue;
-                }
-            }
-            _ => continue,
-        };
-        let comment: String = match &row[6] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => continue,
-        };
+    params.append_uint64(minutes_timeout);
 
-        comments.push(Comment {
-            comment_id,
-            username,
-            userurl,
-            useravatar,
-            create_date: create_date
-                .assume_offset(utc_offset)
-                .format(&output_format)?,
-            edit_date: edit_date.assume_offset(utc_offset).format(&output_format)?,
-            comment,
-        });
-    }
+    conn.query_with_params_drop(
+        "DELETE FROM COMMENT_EVENT WHERE TIMESTAMPDIFF(MINUTE, creation_date, CURRENT_TIMESTAMP) > ?",
+        &params,
+    )?;
 
-    Ok(comments)
+    get_affected_rows(&mut conn)
 }
 
-pub fn get_blog_id_by_comment_id(sql_ctx: SQLCtx, cid: &str) -> Result<String, Error> {
+pub fn cleanup_pending_actions(sql_ctx: SQLCtx) -> Result<u64, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
-    let mut params = MSQLParamsWrapper::new();
-    params.append_str(cid)?;
-
-    let rows = conn.query_with_params_rows(
-        "SELECT blog_post_id FROM COMMENT2 WHERE uuid = ? AND timeout_date IS NULL",
-        &params,
-    )?;
+    conn.query_drop("DELETE FROM PENDING_ACTION WHERE expire_date < CURRENT_TIMESTAMP")?;
 
-    if let Some(rows) = rows
-        && rows.len() == 1
-        && rows[0].len() == 1
-    {
-        match &rows[0][0] {
-            msql_ffi::MSQLValueEnum::Error => {
-                Err("Internal Error blog id not valid in query".into())
-            }
-            msql_ffi::MSQLValueEnum::Null => {
-                Err("Internal Error blog id not valid in query".into())
-            }
-            msql_ffi::MSQLValueEnum::Int64(_) => {
-                Err("Internal Error blog id not valid in query".into())
-            }
-            msql_ffi::MSQLValueEnum::UInt64(_) => {
-                Err("Internal Error blog id not valid in query".into())
-            }
-            msql_ffi::MSQLValueEnum::String(s) => Ok(s.to_owned()),
-            msql_ffi::MSQLValueEnum::DoubleF64(_) => {
-                Err("Internal Error blog id not valid in query".into())
-            }
-        }
-    } else {
-        Err("Internal Error failed to query blog id by comment id".into())
-    }
+    get_affected_rows(&mut conn)
 }
 
-pub fn cleanup_logins(sql_ctx: SQLCtx, minutes_timeout: u64) -> Result<(), Error> {
+pub fn cleanup_logins(sql_ctx: SQLCtx, minutes_timeout: u64) -> Result<u64, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
@@ -703,7 +2725,7 @@ pub fn cleanup_logins(sql_ctx: SQLCtx, minutes_timeout: u64) -> Result<(), Error
         &params,
     )?;
 
-    Ok(())
+    get_affected_rows(&mut conn)
 }
 
 pub fn add_login(
@@ -720,12 +2742,14 @@ pub fn add_login(
         .try_lock()
         .map_err(|_| -> Error { "Failed to get unique connection".into() })?;
 
+    let mut tx = Transaction::begin(&mut conn)?;
+
     let mut id: String = uuid::Uuid::new_v4().to_string();
 
     loop {
         let mut params = MSQLParamsWrapper::new();
         params.append_str(&id)?;
-        let ret = conn.query_with_params_rows("SELECT id FROM LOGIN2 WHERE id = ?", &params)?;
+        let ret = tx.query_with_params_rows("SELECT id FROM LOGIN2 WHERE id = ?", &params)?;
         if ret.is_none() {
             break;
         } else {
@@ -746,12 +2770,19 @@ pub fn add_login(
     params.append_str(userurl)?;
     params.append_str(useravatar)?;
 
-    conn.query_with_params_drop("INSERT INTO LOGIN2 (id, ip, user_id, username, userlogin, userurl, useravatar) VALUES (?, ?, ?, ?, ? ,? ,?)", &params)?;
+    tx.query_with_params_drop("INSERT INTO LOGIN2 (id, ip, user_id, username, userlogin, userurl, useravatar) VALUES (?, ?, ?, ?, ? ,? ,?)", &params)?;
+
+    tx.commit()?;
 
     Ok(id)
 }
 
-pub fn check_logged_in(sql_ctx: SQLCtx, id: &str, ip: &str) -> Result<Option<LoginInfo>, Error> {
+pub fn check_logged_in(
+    sql_ctx: SQLCtx,
+    id: &str,
+    ip: &str,
+    minutes_timeout: u64,
+) -> Result<Option<LoginInfo>, Error> {
     let conn: Arc<Mutex<MSQLWrapper>> = sql_ctx.try_into()?;
     let mut conn = conn
         .try_lock()
@@ -760,53 +2791,11 @@ pub fn check_logged_in(sql_ctx: SQLCtx, id: &str, ip: &str) -> Result<Option<Log
     let mut params = MSQLParamsWrapper::new();
     params.append_str(id)?;
     params.append_str(ip)?;
-    let ret = conn.query_with_params_rows("SELECT id, ip, user_id, username, userlogin, userurl, useravatar FROM LOGIN2 WHERE id = ? AND ip = ?", &params)?;
+    params.append_uint64(minutes_timeout);
+    let ret = conn.query_with_params_rows("SELECT id, ip, user_id, username, userlogin, userurl, useravatar FROM LOGIN2 WHERE id = ? AND ip = ? AND TIMESTAMPDIFF(MINUTE, login_date, CURRENT_TIMESTAMP) <= ?", &params)?;
 
     if let Some(rows) = ret {
-        if rows[0].len() != 7 {
-            return Err(
-                "check_logged_in: Failed due to invalid number of cols returned by query!".into(),
-            );
-        }
-        let user_id = match &rows[0][0] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => return Err("Invalid user_id from db!".into()),
-        };
-        let user_ip = match &rows[0][1] {
-            msql_ffi::MSQLValueEnum::Null => None,
-            msql_ffi::MSQLValueEnum::String(s) => Some(s.to_owned()),
-            _ => return Err("Invalid user_ip from db!".into()),
-        };
-        let user_github_id = match &rows[0][2] {
-            msql_ffi::MSQLValueEnum::Int64(i) => *i as u64,
-            msql_ffi::MSQLValueEnum::UInt64(u) => *u,
-            _ => return Err("Invalid user_github_id from db!".into()),
-        };
-        let username = match &rows[0][3] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => return Err("Invalid username from db!".into()),
-        };
-        let userlogin = match &rows[0][4] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => return Err("Invalid userlogin from db!".into()),
-        };
-        let userurl = match &rows[0][5] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => return Err("Invalid userurl from db!".into()),
-        };
-        let useravatar = match &rows[0][6] {
-            msql_ffi::MSQLValueEnum::String(s) => s.to_owned(),
-            _ => return Err("Invalid useravatar from db!".into()),
-        };
-        Ok(Some(LoginInfo {
-            user_id,
-            user_ip,
-            user_github_id,
-            username,
-            userlogin,
-            userurl,
-            useravatar,
-        }))
+        Ok(Some(LoginInfo::from_row(&rows[0])?))
     } else {
         Ok(None)
     }
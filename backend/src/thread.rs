@@ -0,0 +1,112 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::sql::Comment;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommentNode {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub replies: Vec<CommentNode>,
+}
+
+// Orders comments (given in creation order) so that each reply directly
+// follows its parent and earlier siblings' replies, and sets their depth.
+// Replies whose parent no longer exists are treated as top-level comments.
+// If "hide_deleted" is set, deleted comments are only kept (as placeholders)
+// when they still have replies that are kept.
+pub fn arrange(comments: Vec<Comment>, hide_deleted: bool) -> Vec<Comment> {
+    let ids: HashMap<String, usize> = comments
+        .iter()
+        .enumerate()
+        .map(|(idx, comment)| (comment.comment_id.clone(), idx))
+        .collect();
+
+    let mut roots: Vec<usize> = Vec::new();
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); comments.len()];
+    for (idx, comment) in comments.iter().enumerate() {
+        match comment.parent_id.as_ref().and_then(|pid| ids.get(pid)) {
+            Some(parent_idx) if *parent_idx != idx => children[*parent_idx].push(idx),
+            _ => roots.push(idx),
+        }
+    }
+    // Pinned threads go first, otherwise in creation order.
+    roots.sort_by_key(|idx| !comments[*idx].pinned);
+
+    let mut order: Vec<(usize, u64)> = Vec::with_capacity(comments.len());
+    let mut stack: Vec<(usize, u64)> = roots.iter().rev().map(|idx| (*idx, 0)).collect();
+    while let Some((idx, depth)) = stack.pop() {
+        order.push((idx, depth));
+        stack.extend(children[idx].iter().rev().map(|child| (*child, depth + 1)));
+    }
+
+    if hide_deleted {
+        // Replies come after their parent in "order", so iterating in reverse
+        // visits replies before their parents.
+        let mut keep: Vec<bool> = vec![false; comments.len()];
+        for (idx, _) in order.iter().rev() {
+            let has_kept_reply = children[*idx].iter().any(|child| keep[*child]);
+            keep[*idx] = !comments[*idx].deleted || has_kept_reply;
+        }
+        order.retain(|(idx, _)| keep[*idx]);
+    }
+
+    let mut slots: Vec<Option<Comment>> = comments.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .filter_map(|(idx, depth)| {
+            slots[idx].take().map(|mut comment| {
+                comment.depth = depth;
+                comment
+            })
+        })
+        .collect()
+}
+
+// Expects comments as returned by "arrange".
+pub fn to_tree(comments: Vec<Comment>) -> Vec<CommentNode> {
+    let mut roots: Vec<CommentNode> = Vec::new();
+    // The chain of nodes from a root to the most recent comment.
+    let mut path: Vec<CommentNode> = Vec::new();
+
+    for comment in comments {
+        while path.len() as u64 > comment.depth {
+            let node = path.pop().unwrap();
+            attach(&mut roots, &mut path, node);
+        }
+        path.push(CommentNode {
+            comment,
+            replies: Vec::new(),
+        });
+    }
+    while let Some(node) = path.pop() {
+        attach(&mut roots, &mut path, node);
+    }
+
+    roots
+}
+
+fn attach(roots: &mut Vec<CommentNode>, path: &mut [CommentNode], node: CommentNode) {
+    if let Some(parent) = path.last_mut() {
+        parent.replies.push(node);
+    } else {
+        roots.push(node);
+    }
+}
@@ -0,0 +1,148 @@
+// ISC License
+//
+// Copyright (c) 2025-2026 Stephen Seo
+//
+// Permission to use, copy, modify, and/or distribute this software for any
+// purpose with or without fee is hereby granted, provided that the above
+// copyright notice and this permission notice appear in all copies.
+//
+// THE SOFTWARE IS PROVIDED "AS IS" AND THE AUTHOR DISCLAIMS ALL WARRANTIES WITH
+// REGARD TO THIS SOFTWARE INCLUDING ALL IMPLIED WARRANTIES OF MERCHANTABILITY
+// AND FITNESS. IN NO EVENT SHALL THE AUTHOR BE LIABLE FOR ANY SPECIAL, DIRECT,
+// INDIRECT, OR CONSEQUENTIAL DAMAGES OR ANY DAMAGES WHATSOEVER RESULTING FROM
+// LOSS OF USE, DATA OR PROFITS, WHETHER IN AN ACTION OF CONTRACT, NEGLIGENCE OR
+// OTHER TORTIOUS ACTION, ARISING OUT OF OR IN CONNECTION WITH THE USE OR
+// PERFORMANCE OF THIS SOFTWARE.
+
+use unicode_normalization::UnicodeNormalization;
+use unicode_security::skeleton;
+
+// Characters that change the direction of the text around them.
+fn is_bidi_control(c: char) -> bool {
+    matches!(
+        c,
+        '\u{061C}' | '\u{200E}' | '\u{200F}' | '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}'
+    )
+}
+
+// Characters that take up no space and show nothing, or look like blanks
+// without being whitespace.
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00AD}'
+            | '\u{034F}'
+            | '\u{115F}'
+            | '\u{1160}'
+            | '\u{17B4}'
+            | '\u{17B5}'
+            | '\u{180E}'
+            | '\u{200B}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{206A}'..='\u{206F}'
+            | '\u{3164}'
+            | '\u{FEFF}'
+            | '\u{FFA0}'
+    )
+}
+
+fn is_allowed(c: char) -> bool {
+    !c.is_control() && !is_bidi_control(c) && !is_invisible(c)
+}
+
+// Keeps newlines and tabs, so "\r\n" becomes "\n". Zero width joiners are
+// only kept between visible characters, as emoji and some scripts need them.
+pub fn normalize_comment(comment: &str) -> String {
+    let chars: Vec<char> = comment.chars().collect();
+    let is_visible = |idx: usize| -> bool {
+        chars
+            .get(idx)
+            .is_some_and(|&c| is_allowed(c) && !c.is_whitespace())
+    };
+
+    let mut stripped: String = String::with_capacity(comment.len());
+    for (idx, &c) in chars.iter().enumerate() {
+        let keep: bool = match c {
+            '\n' | '\t' => true,
+            '\u{200C}' | '\u{200D}' => idx > 0 && is_visible(idx - 1) && is_visible(idx + 1),
+            _ => is_allowed(c),
+        };
+        if keep {
+            stripped.push(c);
+        }
+    }
+
+    stripped.nfc().collect()
+}
+
+// Also collapses whitespace into single spaces, and trims it.
+pub fn normalize_name(name: &str) -> String {
+    let stripped: String = name.chars().filter(|&c| is_allowed(c)).nfc().collect();
+
+    stripped.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+// Compares the UTS #39 skeletons, ignoring case and anything that isn't a
+// letter or digit. "Some-Admin" is confusable with "some admin", and with
+// names that swap in look-alike letters from other scripts.
+pub fn is_confusable(a: &str, b: &str) -> bool {
+    let a: String = confusable_key(a);
+
+    !a.is_empty() && a == confusable_key(b)
+}
+
+fn confusable_key(name: &str) -> String {
+    skeleton(&name.to_lowercase())
+        .flat_map(char::to_lowercase)
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confusable_punctuation_and_case() {
+        assert!(is_confusable("Some-Admin", "some admin"));
+        assert!(is_confusable("SomeAdmin", "some_admin"));
+        assert!(!is_confusable("Some-Admin", "other admin"));
+        assert!(!is_confusable("---", "___"));
+    }
+
+    #[test]
+    fn confusable_other_scripts() {
+        // Cyrillic "а" and "о".
+        assert!(is_confusable("\u{0430}dmin", "admin"));
+        assert!(is_confusable("r\u{043E}\u{043E}t", "root"));
+        assert!(!is_confusable("\u{0430}dmin", "user"));
+    }
+
+    #[test]
+    fn keeps_emoji_sequences() {
+        let family: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        assert_eq!(normalize_comment(family), family);
+        assert_eq!(
+            normalize_comment(&format!("a {} b", family)),
+            format!("a {} b", family)
+        );
+    }
+
+    #[test]
+    fn strips_stray_joiners() {
+        assert_eq!(normalize_comment("\u{200D}hi\u{200D}"), "hi");
+        assert_eq!(normalize_comment("a \u{200D} b"), "a  b");
+        assert_eq!(normalize_comment("a\u{200B}b"), "ab");
+    }
+
+    #[test]
+    fn strips_bidi_controls() {
+        assert_eq!(normalize_comment("a\u{202E}b\u{2066}c\u{2069}"), "abc");
+        assert_eq!(normalize_name(" \u{202E}Some\u{200F}  Name "), "Some Name");
+    }
+
+    #[test]
+    fn keeps_line_breaks() {
+        assert_eq!(normalize_comment("a\r\nb\tc"), "a\nb\tc");
+    }
+}
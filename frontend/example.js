@@ -9,11 +9,18 @@ async function load_blog_comments(blog_id, blog_url, base_url) {
 
   let comment_div = document.getElementById(blog_id);
   comment_div.innerHTML = "";
-  const response = await fetch(base_url + "/get_comments?blog_id=" + blog_id);
+  // The login_id is only used to mark your own reactions.
+  let viewer_login_id = window.localStorage.getItem("seodisp_comments_login_id");
+  let viewer_part = "";
+  if (viewer_login_id !== null) {
+    viewer_part = "&login_id=" + viewer_login_id;
+  }
+  const response = await fetch(base_url + "/get_comments?blog_id=" + blog_id + viewer_part);
   if (!response.ok) {
     comment_div.innerText = "ERROR: Failed to fetch comments!";
     return;
   }
+  let comments_closed = response.headers.get("X-Comments-Closed") === "true";
   try {
     let json_arr = JSON.parse(await response.text());
     if (json_arr.length == 0) {
@@ -57,6 +64,10 @@ async function load_blog_comments(blog_id, blog_url, base_url) {
       for (let idx = 0; idx < json_arr.length; ++idx) {
         let br_elem = document.createElement("br");
         comment_div.appendChild(br_elem);
+        // Replies are indented by their depth.
+        let thread_div = document.createElement("div");
+        thread_div.setAttribute("style", "margin-left: " + (json_arr[idx].depth * 2) + "em;");
+        comment_div.appendChild(thread_div);
         let a_img = document.createElement("a");
         a_img.setAttribute("href", "#" + blog_id + "comment" + json_arr[idx].comment_id);
         a_img.setAttribute("id", blog_id + "comment" + json_arr[idx].comment_id);
@@ -65,46 +76,97 @@ async function load_blog_comments(blog_id, blog_url, base_url) {
         img_elem.setAttribute("height", "64");
         img_elem.setAttribute("src", json_arr[idx].useravatar);
         a_img.appendChild(img_elem);
-        comment_div.appendChild(a_img);
+        thread_div.appendChild(a_img);
         let bold_elem = document.createElement("b");
         bold_elem.innerText = json_arr[idx].username;
-        comment_div.appendChild(bold_elem);
+        for (let badge of ["admin", "moderator", "author"]) {
+          if (json_arr[idx][badge]) {
+            bold_elem.innerText += " [" + badge + "]";
+          }
+        }
+        if (json_arr[idx].pinned) {
+          bold_elem.innerText += " (pinned)";
+        }
+        thread_div.appendChild(bold_elem);
         let link_elem = document.createElement("a");
         link_elem.setAttribute("href", json_arr[idx].userurl);
         link_elem.innerText = "(User Profile)";
-        comment_div.appendChild(link_elem);
+        thread_div.appendChild(link_elem);
         let create_time_bold = document.createElement("b");
         let create_time_obj = new Date(json_arr[idx].create_date);
         let edit_time_obj = new Date(json_arr[idx].edit_date);
         create_time_bold.innerText = "Created: " + create_time_obj.toString();
         create_time_bold.innerText += ", Edited: " + edit_time_obj.toString();
-        comment_div.appendChild(document.createElement("br"));
-        comment_div.appendChild(create_time_bold);
+        thread_div.appendChild(document.createElement("br"));
+        thread_div.appendChild(create_time_bold);
         let comment_text = document.createElement("div");
-        comment_text.innerText = json_arr[idx].comment;
-        comment_div.appendChild(comment_text);
-        let edit_button = document.createElement("button");
-        edit_button.innerText = "Edit";
-        edit_button.onclick = (e) => {
-          let login_id = window.localStorage.getItem("seodisp_comments_login_id");
-          let login_part = "";
-          if (login_id !== null) {
-              login_part = "&login_id=" + login_id;
+        // comment_html is sanitized by the server.
+        comment_text.innerHTML = json_arr[idx].comment_html;
+        thread_div.appendChild(comment_text);
+        if (json_arr[idx].reactions.length > 0) {
+          let reactions_div = document.createElement("div");
+          for (let reaction of json_arr[idx].reactions) {
+            let reaction_button = document.createElement("button");
+            reaction_button.innerText = reaction.emoji + " " + reaction.count;
+            if (reaction.reacted) {
+              reaction_button.setAttribute("style", "font-weight: bold;");
+            }
+            // Reacting requires being logged in already.
+            reaction_button.disabled = comments_closed || viewer_login_id === null;
+            reaction_button.onclick = async (e) => {
+              let endpoint = reaction.reacted ? "/remove_reaction" : "/add_reaction";
+              await fetch(base_url + endpoint, {
+                method: "POST",
+                headers: {"Content-Type": "application/json", "Accept": "application/json"},
+                body: JSON.stringify({
+                  login_id: viewer_login_id,
+                  comment_id: json_arr[idx].comment_id,
+                  emoji: reaction.emoji,
+                }),
+              });
+              await load_blog_comments(blog_id, blog_url, base_url);
+            };
+            reactions_div.appendChild(reaction_button);
           }
-          window.location = base_url + "/edit_comment?comment_id=" + json_arr[idx].comment_id + "&blog_url=" + blog_url_encoded + login_part;
-        };
-        comment_div.appendChild(edit_button);
-        let delete_button = document.createElement("button");
-        delete_button.innerText = "Delete";
-        delete_button.onclick = (e) => {
-          let login_id = window.localStorage.getItem("seodisp_comments_login_id");
-          let login_part = "";
-          if (login_id !== null) {
-              login_part = "&login_id=" + login_id;
+          thread_div.appendChild(reactions_div);
+        }
+        if (!json_arr[idx].deleted) {
+          if (!comments_closed) {
+            let reply_button = document.createElement("button");
+            reply_button.innerText = "Reply";
+            reply_button.onclick = (e) => {
+              let login_id = window.localStorage.getItem("seodisp_comments_login_id");
+              let login_part = "";
+              if (login_id !== null) {
+                  login_part = "&login_id=" + login_id;
+              }
+              window.location = base_url + "/do_comment?blog_id=" + blog_id + "&blog_url=" + blog_url_encoded + "&parent_id=" + json_arr[idx].comment_id + login_part;
+            };
+            thread_div.appendChild(reply_button);
+            let edit_button = document.createElement("button");
+            edit_button.innerText = "Edit";
+            edit_button.onclick = (e) => {
+              let login_id = window.localStorage.getItem("seodisp_comments_login_id");
+              let login_part = "";
+              if (login_id !== null) {
+                  login_part = "&login_id=" + login_id;
+              }
+              window.location = base_url + "/edit_comment?comment_id=" + json_arr[idx].comment_id + "&blog_url=" + blog_url_encoded + login_part;
+            };
+            thread_div.appendChild(edit_button);
           }
-          window.location = base_url + "/del_comment?comment_id=" + json_arr[idx].comment_id + "&blog_url=" + blog_url_encoded + login_part;
-        };
-        comment_div.appendChild(delete_button);
+          let delete_button = document.createElement("button");
+          delete_button.innerText = "Delete";
+          delete_button.onclick = (e) => {
+            let login_id = window.localStorage.getItem("seodisp_comments_login_id");
+            let login_part = "";
+            if (login_id !== null) {
+                login_part = "&login_id=" + login_id;
+            }
+            window.location = base_url + "/del_comment?comment_id=" + json_arr[idx].comment_id + "&blog_url=" + blog_url_encoded + login_part;
+          };
+          thread_div.appendChild(delete_button);
+        }
       }
     }
     if (window.location.hash.length > 1) {
@@ -121,6 +183,12 @@ async function load_blog_comments(blog_id, blog_url, base_url) {
 
   comment_div.appendChild(document.createElement("br"));
   comment_div.appendChild(document.createElement("br"));
+  if (comments_closed) {
+    let closed_elem = document.createElement("b");
+    closed_elem.innerText = "Comments are closed.";
+    comment_div.appendChild(closed_elem);
+    return;
+  }
   let new_comment_button = document.createElement("button");
   new_comment_button.onclick = (e) => {
     let login_id = window.localStorage.getItem("seodisp_comments_login_id");
@@ -133,3 +201,15 @@ async function load_blog_comments(blog_id, blog_url, base_url) {
   new_comment_button.innerText = "Submit a New Comment";
   comment_div.appendChild(new_comment_button);
 }
+
+// Reloads the comments whenever one is created, edited or deleted. The browser
+// reconnects on its own if the connection drops.
+function watch_blog_comments(blog_id, blog_url, base_url) {
+  let events = new EventSource(base_url + "/comment_events?blog_id=" + encodeURIComponent(blog_id));
+  for (let name of ["created", "edited", "deleted", "reload"]) {
+    events.addEventListener(name, (e) => {
+      load_blog_comments(blog_id, blog_url, base_url);
+    });
+  }
+  return events;
+}